@@ -1,6 +1,8 @@
 // Copyright 2024 System76 <info@system76.com>
 // SPDX-License-Identifier: GPL-3.0-only
 
+pub mod session;
+pub mod state;
 pub mod user;
 
 use cosmic_config::CosmicConfigEntry;
@@ -19,19 +21,47 @@ pub struct Config {
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     pub users: HashMap<NonZeroU32, user::UserState>,
     pub last_user: Option<NonZeroU32>,
+    /// Blur applied to wallpapers so they are not recognizable, in pixels. Disabled if zero.
+    pub wallpaper_blur_radius: f32,
+    /// Opacity of a black layer drawn over wallpapers, from 0 to 1. Disabled if zero.
+    pub wallpaper_dim: f32,
+    /// Only show the login prompt on the focused or primary output, other outputs only show
+    /// the clock.
+    pub single_output_prompt: bool,
+    /// Session for users that have not logged in before and have no session set in
+    /// AccountsService, as a desktop file id like `cosmic` or a session name. The first
+    /// session in alphabetical order is used if not set.
+    pub default_session: Option<String>,
+    /// Sessions listed next to the ones from `wayland-sessions` and `xsessions` desktop files,
+    /// replacing desktop files with the same id and session type.
+    pub custom_sessions: Vec<session::CustomSession>,
+    /// Command that runs every session, which is passed as its arguments. Like the
+    /// `session-wrapper` of LightDM, it must be an absolute path.
+    pub session_wrapper: Option<String>,
+    /// User logged in without a password when the greeter starts for the first time after
+    /// boot. Cancelling the countdown shows the last user or the user picker instead.
+    pub autologin: Option<session::Autologin>,
 }
 
 impl Config {
     pub fn load() -> (Self, Option<cosmic_config::Config>) {
-        crate::load()
+        crate::load(cosmic_config::Config::new(APP_ID, CONFIG_VERSION))
+    }
+
+    /// Loads the system config only. The lock screen runs as the locked user, who must not be
+    /// able to change its settings through their own config.
+    pub fn load_system() -> (Self, Option<cosmic_config::Config>) {
+        crate::load(cosmic_config::Config::system(APP_ID, CONFIG_VERSION))
     }
 }
 
-pub(crate) fn load<C>() -> (C, Option<cosmic_config::Config>)
+pub(crate) fn load<C>(
+    handler_res: Result<cosmic_config::Config, cosmic_config::Error>,
+) -> (C, Option<cosmic_config::Config>)
 where
     C: Default + CosmicConfigEntry,
 {
-    match cosmic_config::Config::new(APP_ID, CONFIG_VERSION) {
+    match handler_res {
         Ok(handler) => {
             let config = C::get_entry(&handler)
                 .inspect_err(|(errors, _)| {
@@ -0,0 +1,40 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: GPL-3.0-only
+
+use serde::{Deserialize, Serialize};
+
+use crate::user::SessionType;
+
+/// Session defined by an administrator instead of a desktop file
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CustomSession {
+    /// Identifies the session like the id of a desktop file, in user state and
+    /// `default_session`
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub comment: Option<String>,
+    /// Icon name or absolute path
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Program and arguments
+    pub exec: Vec<String>,
+    /// Environment variables, like `MOZ_ENABLE_WAYLAND=1`
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(default)]
+    pub session_type: SessionType,
+}
+
+/// Logs a user into a session when the greeter starts for the first time after boot. The PAM
+/// service of greetd must let the user in without asking for a password.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Autologin {
+    pub user: String,
+    /// Session id, the session of the user is picked like for other logins if not set
+    #[serde(default)]
+    pub session: Option<String>,
+    /// Seconds to wait before logging in, during which any key cancels it
+    #[serde(default)]
+    pub delay: u32,
+}
@@ -0,0 +1,59 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic_config::CosmicConfigEntry;
+use cosmic_config::cosmic_config_derive::CosmicConfigEntry;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU32;
+use std::time::{Duration, SystemTime};
+
+use crate::user::SessionType;
+
+/// A session started by the greeter
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SessionStart {
+    pub uid: NonZeroU32,
+    /// Desktop file id of the session
+    pub session: String,
+    pub session_type: SessionType,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+}
+
+impl SessionStart {
+    pub fn new(uid: NonZeroU32, session: String, session_type: SessionType) -> Self {
+        Self {
+            uid,
+            session,
+            session_type,
+            started_at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs()),
+        }
+    }
+
+    /// Time since the session was started, `None` if the clock went backwards
+    pub fn elapsed(&self) -> Option<Duration> {
+        (SystemTime::UNIX_EPOCH + Duration::from_secs(self.started_at))
+            .elapsed()
+            .ok()
+    }
+}
+
+/// State written by the greeter, as opposed to settings written by administrators
+#[derive(Debug, Clone, Default, PartialEq, CosmicConfigEntry, Deserialize, Serialize)]
+#[version = 1]
+#[id = "com.system76.CosmicGreeter"]
+pub struct State {
+    /// Last session started, cleared when the greeter starts again
+    pub last_session_start: Option<SessionStart>,
+}
+
+impl State {
+    pub fn load() -> (Self, Option<cosmic_config::Config>) {
+        crate::load(cosmic_config::Config::new_state(
+            crate::APP_ID,
+            crate::CONFIG_VERSION,
+        ))
+    }
+}
@@ -4,10 +4,23 @@
 use serde::{Deserialize, Serialize};
 use std::num::NonZeroU32;
 
+/// Display server of a session
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum SessionType {
+    #[default]
+    Wayland,
+    X11,
+}
+
 /// Per user state for Greeter.
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct UserState {
     pub uid: NonZeroU32,
+    /// Desktop file id of the last session, older versions stored the session name
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_session: Option<String>,
+    /// Display server of the last session, used to pick the same variant of a desktop when it
+    /// has both Wayland and X11 sessions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_session_type: Option<SessionType>,
 }
@@ -0,0 +1,268 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use zbus::zvariant::OwnedObjectPath;
+
+#[zbus::proxy(
+    interface = "org.freedesktop.Accounts",
+    default_service = "org.freedesktop.Accounts",
+    default_path = "/org/freedesktop/Accounts"
+)]
+pub trait Accounts {
+    fn find_user_by_id(&self, id: i64) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.Accounts.User",
+    default_service = "org.freedesktop.Accounts"
+)]
+pub trait AccountsUser {
+    #[zbus(property)]
+    fn icon_file(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn real_name(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn language(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn session(&self) -> zbus::Result<String>;
+
+    #[zbus(property, name = "XSession")]
+    fn x_session(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn system_account(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn locked(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn password_mode(&self) -> zbus::Result<i32>;
+}
+
+/// How the password of an account is handled, as reported by AccountsService.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+pub enum PasswordMode {
+    #[default]
+    Regular,
+    SetAtLogin,
+    None,
+}
+
+impl From<i32> for PasswordMode {
+    fn from(mode: i32) -> Self {
+        match mode {
+            1 => Self::SetAtLogin,
+            2 => Self::None,
+            _ => Self::Regular,
+        }
+    }
+}
+
+/// User metadata from `org.freedesktop.Accounts`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AccountInfo {
+    pub icon_file: Option<PathBuf>,
+    pub real_name: Option<String>,
+    pub language: Option<String>,
+    /// Preferred session desktop file id
+    pub session: Option<String>,
+    /// Preferred X11 session desktop file id
+    pub x_session: Option<String>,
+    pub system_account: bool,
+    pub locked: bool,
+    pub password_mode: PasswordMode,
+}
+
+impl AccountInfo {
+    pub async fn load(connection: &zbus::Connection, uid: u32) -> zbus::Result<Self> {
+        let accounts = AccountsProxy::new(connection).await?;
+        let path = accounts.find_user_by_id(uid.into()).await?;
+        let user = AccountsUserProxy::builder(connection)
+            .path(path)?
+            .build()
+            .await?;
+
+        // AccountsService uses empty strings for unset values, and older versions may not
+        // provide all properties
+        fn non_empty(res: zbus::Result<String>) -> Option<String> {
+            res.ok().filter(|value| !value.is_empty())
+        }
+
+        Ok(Self {
+            icon_file: non_empty(user.icon_file().await).map(PathBuf::from),
+            real_name: non_empty(user.real_name().await),
+            language: non_empty(user.language().await),
+            session: non_empty(user.session().await),
+            x_session: non_empty(user.x_session().await),
+            system_account: user.system_account().await.unwrap_or_default(),
+            locked: user.locked().await.unwrap_or_default(),
+            password_mode: user.password_mode().await.unwrap_or_default().into(),
+        })
+    }
+
+    /// Accounts that should not be shown in the user list
+    pub fn is_hidden(&self) -> bool {
+        self.system_account || self.locked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use zbus::connection::Builder;
+    use zbus::zvariant::ObjectPath;
+
+    struct StubAccounts {
+        uids: Vec<u32>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Accounts")]
+    impl StubAccounts {
+        fn find_user_by_id(&self, id: i64) -> zbus::fdo::Result<OwnedObjectPath> {
+            if !self.uids.iter().any(|uid| i64::from(*uid) == id) {
+                return Err(zbus::fdo::Error::Failed(format!("no user with uid {id}")));
+            }
+            Ok(
+                ObjectPath::try_from(format!("/org/freedesktop/Accounts/User{id}"))
+                    .unwrap()
+                    .into(),
+            )
+        }
+    }
+
+    #[derive(Default)]
+    struct StubUser {
+        session: String,
+        system_account: bool,
+        locked: bool,
+        password_mode: i32,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Accounts.User")]
+    impl StubUser {
+        #[zbus(property)]
+        fn icon_file(&self) -> String {
+            "/var/lib/AccountsService/icons/user".to_string()
+        }
+
+        #[zbus(property)]
+        fn real_name(&self) -> String {
+            "Stub User".to_string()
+        }
+
+        #[zbus(property)]
+        fn language(&self) -> String {
+            String::new()
+        }
+
+        #[zbus(property)]
+        fn session(&self) -> String {
+            self.session.clone()
+        }
+
+        #[zbus(property, name = "XSession")]
+        fn x_session(&self) -> String {
+            String::new()
+        }
+
+        #[zbus(property)]
+        fn system_account(&self) -> bool {
+            self.system_account
+        }
+
+        #[zbus(property)]
+        fn locked(&self) -> bool {
+            self.locked
+        }
+
+        #[zbus(property)]
+        fn password_mode(&self) -> i32 {
+            self.password_mode
+        }
+    }
+
+    /// Serves the stub users on a peer to peer connection, returning the client side
+    async fn connect(users: Vec<(u32, StubUser)>) -> (zbus::Connection, zbus::Connection) {
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+        let mut server = Builder::unix_stream(server_stream)
+            .server(zbus::Guid::generate())
+            .unwrap()
+            .p2p()
+            .serve_at(
+                "/org/freedesktop/Accounts",
+                StubAccounts {
+                    uids: users.iter().map(|(uid, _)| *uid).collect(),
+                },
+            )
+            .unwrap();
+        for (uid, user) in users {
+            server = server
+                .serve_at(format!("/org/freedesktop/Accounts/User{uid}"), user)
+                .unwrap();
+        }
+        let client = Builder::unix_stream(client_stream).p2p();
+        let (server, client) = tokio::join!(server.build(), client.build());
+        (server.unwrap(), client.unwrap())
+    }
+
+    #[tokio::test]
+    async fn load_account() {
+        let user = StubUser {
+            session: "cosmic".to_string(),
+            password_mode: 1,
+            ..Default::default()
+        };
+        let (_server, client) = connect(vec![(1000, user)]).await;
+
+        let account = AccountInfo::load(&client, 1000).await.unwrap();
+        assert_eq!(
+            account.icon_file,
+            Some(PathBuf::from("/var/lib/AccountsService/icons/user"))
+        );
+        assert_eq!(account.real_name.as_deref(), Some("Stub User"));
+        // Empty strings are unset values
+        assert_eq!(account.language, None);
+        assert_eq!(account.session.as_deref(), Some("cosmic"));
+        assert_eq!(account.x_session, None);
+        assert_eq!(account.password_mode, PasswordMode::SetAtLogin);
+        assert!(!account.is_hidden());
+    }
+
+    #[tokio::test]
+    async fn hidden_accounts() {
+        let (_server, client) = connect(vec![
+            (
+                1001,
+                StubUser {
+                    system_account: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                1002,
+                StubUser {
+                    locked: true,
+                    ..Default::default()
+                },
+            ),
+        ])
+        .await;
+
+        let system = AccountInfo::load(&client, 1001).await.unwrap();
+        assert!(system.system_account);
+        assert!(system.is_hidden());
+
+        let locked = AccountInfo::load(&client, 1002).await.unwrap();
+        assert!(locked.locked);
+        assert!(locked.is_hidden());
+    }
+
+    #[tokio::test]
+    async fn unknown_account() {
+        let (_server, client) = connect(Vec::new()).await;
+        assert!(AccountInfo::load(&client, 1000).await.is_err());
+    }
+}
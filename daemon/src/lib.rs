@@ -7,9 +7,15 @@ use std::io::Read;
 use std::os::unix::fs::OpenOptionsExt;
 use std::path::{Path, PathBuf};
 
+pub mod accounts;
+pub mod wallpaper;
+
+pub use accounts::{AccountInfo, PasswordMode};
 pub use cosmic_applets_config::time::TimeAppletConfig;
 pub use cosmic_bg_config::state::State as BgState;
-pub use cosmic_bg_config::{Color, Source as BgSource};
+pub use cosmic_bg_config::{
+    Color, Entry as BgEntry, ScalingMode as BgScalingMode, Source as BgSource,
+};
 pub use cosmic_comp_config::{CosmicCompConfig, XkbConfig, ZoomConfig};
 pub use cosmic_theme::{Theme, ThemeBuilder};
 
@@ -61,10 +67,14 @@ pub struct UserData {
     pub uid: u32,
     pub name: String,
     pub full_name: String,
+    pub account_opt: Option<AccountInfo>,
     pub icon_opt: Option<Vec<u8>>,
     pub theme_opt: Option<Theme>,
     pub theme_builder_opt: Option<ThemeBuilder>,
     pub bg_state: BgState,
+    pub bg_same_on_all: bool,
+    pub bg_entries: Vec<BgEntry>,
+    pub bg_default_opt: Option<BgEntry>,
     pub bg_path_data: BTreeMap<PathBuf, Vec<u8>>,
     pub xkb_config_opt: Option<XkbConfig>,
     pub time_applet_config: TimeAppletConfig,
@@ -73,25 +83,29 @@ pub struct UserData {
 }
 
 impl UserData {
+    pub fn set_account(&mut self, account: AccountInfo) {
+        if let Some(real_name) = &account.real_name {
+            self.full_name = real_name.clone();
+        }
+        self.account_opt = Some(account);
+    }
+
     pub fn load_wallpapers_as_user(&mut self) {
         //TODO: reload changed background files?
-        self.bg_path_data.retain(|path, _| {
-            self.bg_state
-                .wallpapers
-                .iter()
-                .any(|(_, source)| match source {
-                    BgSource::Path(source_path) => source_path == path,
-                    _ => false,
-                })
-        });
-        for (_, source) in self.bg_state.wallpapers.iter() {
-            //TODO: do not reread duplicate paths, cache data by path?
-            if let BgSource::Path(path) = source
-                && !self.bg_path_data.contains_key(path)
-            {
-                match fs::read(path) {
+        let paths: Vec<PathBuf> = self
+            .bg_sources()
+            .filter_map(|source| match source {
+                BgSource::Path(path) => Some(path.clone()),
+                _ => None,
+            })
+            .collect();
+        self.bg_path_data.retain(|path, _| paths.contains(path));
+        for path in paths {
+            //TODO: support directories
+            if !self.bg_path_data.contains_key(&path) && !path.is_dir() {
+                match fs::read(&path) {
                     Ok(bytes) => {
-                        self.bg_path_data.insert(path.clone(), bytes);
+                        self.bg_path_data.insert(path, bytes);
                     }
                     Err(err) => {
                         tracing::error!("failed to read wallpaper {:?}: {:?}", path, err);
@@ -101,19 +115,129 @@ impl UserData {
         }
     }
 
+    /// All background sources that may be used by an output
+    fn bg_sources(&self) -> impl Iterator<Item = &BgSource> {
+        self.bg_state
+            .wallpapers
+            .iter()
+            .map(|(_, source)| source)
+            .chain(self.bg_entries.iter().map(|entry| &entry.source))
+            .chain(self.bg_default_opt.iter().map(|entry| &entry.source))
+    }
+
+    /// Finds the cosmic-bg config entry of an output
+    pub fn bg_entry(&self, output_name: &str) -> Option<&BgEntry> {
+        if !self.bg_same_on_all
+            && let Some(entry) = self
+                .bg_entries
+                .iter()
+                .find(|entry| entry.output == output_name)
+        {
+            return Some(entry);
+        }
+        self.bg_default_opt.as_ref()
+    }
+
+    /// Finds the background source of an output, preferring the image that cosmic-bg last showed
+    /// on it and falling back to the cosmic-bg config for outputs it has not seen yet
+    pub fn bg_source(&self, output_name: &str) -> Option<&BgSource> {
+        //TODO: what to do about duplicates?
+        let state_source = |name: &str| {
+            self.bg_state
+                .wallpapers
+                .iter()
+                .find(|(wallpaper_output_name, _)| wallpaper_output_name == name)
+                .map(|(_, source)| source)
+        };
+        let source_opt = if self.bg_same_on_all {
+            // Every output shows the same background, so use any output cosmic-bg has seen
+            state_source(output_name)
+                .or_else(|| self.bg_state.wallpapers.first().map(|(_, source)| source))
+        } else {
+            state_source(output_name)
+        };
+        source_opt.or_else(|| self.bg_entry(output_name).map(|entry| &entry.source))
+    }
+
+    /// Finds how the background image of an output is scaled
+    pub fn bg_scaling_mode(&self, output_name: &str) -> BgScalingMode {
+        self.bg_entry(output_name)
+            .map(|entry| entry.scaling_mode.clone())
+            .unwrap_or(BgScalingMode::Zoom)
+    }
+
+    pub fn load_bg_config_as_user(&mut self) {
+        self.bg_same_on_all = false;
+        self.bg_entries.clear();
+        self.bg_default_opt = None;
+
+        match cosmic_bg_config::context() {
+            Ok(context) => match cosmic_bg_config::Config::load(&context) {
+                Ok(config) => {
+                    self.bg_same_on_all = config.same_on_all;
+                    self.bg_entries = config.backgrounds;
+                    self.bg_default_opt = Some(config.default_background);
+                }
+                Err(err) => {
+                    tracing::error!("failed to load cosmic-bg config: {:?}", err);
+                }
+            },
+            Err(err) => {
+                tracing::error!("failed to create cosmic-bg config helper: {:?}", err);
+            }
+        }
+    }
+
+    pub fn load_bg_state_as_user(&mut self) {
+        self.bg_state = Default::default();
+
+        match cosmic_bg_config::state::State::state() {
+            Ok(helper) => match cosmic_bg_config::state::State::get_entry(&helper) {
+                Ok(state) => {
+                    self.bg_state = state;
+                }
+                Err((errs, state)) => {
+                    tracing::error!("failed to load cosmic-bg state: {:?}", errs);
+                    self.bg_state = state;
+                }
+            },
+            Err(err) => {
+                tracing::error!("failed to create cosmic-bg state helper: {:?}", err);
+            }
+        }
+    }
+
+    pub fn load_output_lists_as_user(&mut self) {
+        let xdg = xdg::BaseDirectories::new();
+        self.kdl_output_lists = xdg
+            .get_state_home()
+            .map(|mut s| {
+                s.push("cosmic-comp/outputs.ron");
+                let lists = randr::load_outputs(Some(&s));
+                lists
+                    .into_iter()
+                    .map(|l| KdlDocument::from(l).to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+
     pub fn load_config_as_user(&mut self) {
         self.icon_opt = None;
         self.theme_opt = None;
         self.theme_builder_opt = None;
-        self.bg_state = Default::default();
         self.xkb_config_opt = None;
         self.time_applet_config = Default::default();
 
-        //TODO: use accountsservice?
-        //IMPORTANT: This file is owned by root and safe to read (it won't be a link to /etc/shadow for example)
-        // It may not exist if the user uses one of the system icons. In that case, we should read the
-        // information in /var/lib/AccountsService/users, and then read the icon path as the user
-        let icon_path = Path::new("/var/lib/AccountsService/icons").join(&self.name);
+        //IMPORTANT: This runs as the user, so the icon path provided by AccountsService can not be
+        // used to read files the user does not have access to. When AccountsService is not
+        // available, fall back to its icon directory, which is owned by root and safe to read (it
+        // won't be a link to /etc/shadow for example)
+        let icon_path = self
+            .account_opt
+            .as_ref()
+            .and_then(|account| account.icon_file.clone())
+            .unwrap_or_else(|| Path::new("/var/lib/AccountsService/icons").join(&self.name));
         match fs::OpenOptions::new()
             .read(true)
             // Do not follow symlinks
@@ -194,22 +318,8 @@ impl UserData {
             }
         }
 
-        //TODO: fallback to background config if background state is not set?
-        match cosmic_bg_config::state::State::state() {
-            Ok(helper) => match cosmic_bg_config::state::State::get_entry(&helper) {
-                Ok(state) => {
-                    self.bg_state = state;
-                }
-                Err((errs, state)) => {
-                    tracing::error!("failed to load cosmic-bg state: {:?}", errs);
-                    self.bg_state = state;
-                }
-            },
-            Err(err) => {
-                tracing::error!("failed to create cosmic-bg state helper: {:?}", err);
-            }
-        }
-        self.load_wallpapers_as_user();
+        self.load_bg_config_as_user();
+        self.load_bg_state_as_user();
 
         match cosmic_config::Config::new("com.system76.CosmicComp", CosmicCompConfig::VERSION) {
             Ok(config_handler) => {
@@ -230,18 +340,7 @@ impl UserData {
             }
         };
 
-        let xdg = xdg::BaseDirectories::new();
-        self.kdl_output_lists = xdg
-            .get_state_home()
-            .map(|mut s| {
-                s.push("cosmic-comp/outputs.ron");
-                let lists = randr::load_outputs(Some(&s));
-                lists
-                    .into_iter()
-                    .map(|l| KdlDocument::from(l).to_string())
-                    .collect()
-            })
-            .unwrap_or_default();
+        self.load_output_lists_as_user();
 
         match cosmic_config::Config::new("com.system76.CosmicAppletTime", TimeAppletConfig::VERSION)
         {
@@ -1,15 +1,27 @@
 use color_eyre::eyre::Context;
-use cosmic_greeter_daemon::{UserData, UserFilter};
+use cosmic_greeter_daemon::{
+    AccountInfo, BgScalingMode, BgSource, UserData, UserFilter, wallpaper,
+};
 use std::error::Error;
 use std::ffi::CString;
 use std::future::pending;
-use std::{env, io};
+use std::io::{Read, Seek, Write};
+use std::os::fd::AsFd;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use std::{env, fs, io};
 use tracing::metadata::LevelFilter;
 use tracing::warn;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::{EnvFilter, fmt};
 use zbus::DBusError;
 use zbus::connection::Builder;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::OwnedFd;
+
+mod watch;
+mod worker;
 
 //IMPORTANT: this function is critical to the security of this proxy. It must ensure that the
 // callback is executed with the permissions of the specified user id. A good test is to see if
@@ -64,41 +76,340 @@ enum GreeterError {
     ZBus(zbus::Error),
     Ron(String),
     RunAsUser(String),
+    UnknownUser(u32),
+    Wallpaper(String),
+}
+
+/// Must be called on the worker thread, see [`worker::run`]
+fn users() -> Vec<pwd::Passwd> {
+    let user_filter = UserFilter::new();
+
+    // The pwd::Passwd method is unsafe (but not labelled as such) due to using global state (libc pwent functions).
+    // To prevent issues, this should only be called once in the entire process space at a time
+    /* unsafe */
+    {
+        pwd::Passwd::iter()
+            .filter(|user| user_filter.filter(user))
+            .collect()
+    }
+}
+
+/// Finds a user that may be shown in the greeter, other users are not accessible over D-Bus.
+/// Must be called on the worker thread, see [`worker::run`]
+fn user(uid: u32) -> Result<pwd::Passwd, GreeterError> {
+    users()
+        .into_iter()
+        .find(|user| user.uid == uid)
+        .ok_or(GreeterError::UnknownUser(uid))
+}
+
+/// Creates [`UserData`] with account information, returns `None` if the user should be hidden
+async fn user_data(connection: &zbus::Connection, user: &pwd::Passwd) -> Option<UserData> {
+    let mut user_data = UserData::from(user.clone());
+    match AccountInfo::load(connection, user.uid).await {
+        Ok(account) => {
+            if account.is_hidden() {
+                return None;
+            }
+            user_data.set_account(account);
+        }
+        Err(err) => {
+            tracing::warn!("failed to load account info for {}: {}", user.name, err);
+        }
+    }
+    Some(user_data)
 }
 
 struct GreeterProxy;
 
 #[zbus::interface(name = "com.system76.CosmicGreeter")]
 impl GreeterProxy {
-    fn get_user_data(&mut self) -> Result<String, GreeterError> {
-        let user_filter = UserFilter::new();
-
-        // The pwd::Passwd method is unsafe (but not labelled as such) due to using global state (libc pwent functions).
-        // To prevent issues, this should only be called once in the entire process space at a time
-        let users: Vec<_> = /* unsafe */ {
-             pwd::Passwd::iter()
-                .filter(|user| user_filter.filter(user))
-                .collect()
-            };
+    /// Lists the uid, name, and full name of all users that can log in
+    async fn list_users(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> Result<Vec<(u32, String, String)>, GreeterError> {
+        let users = worker::run(users).await;
 
-        let mut user_datas = Vec::new();
+        let mut list = Vec::with_capacity(users.len());
         for user in users {
-            let mut user_data = UserData::from(user.clone());
+            if let Some(user_data) = user_data(connection, &user).await {
+                list.push((user_data.uid, user_data.name, user_data.full_name));
+            }
+        }
+        Ok(list)
+    }
+
+    /// Emitted when the configuration or icon of a user changes
+    #[zbus(signal)]
+    async fn user_data_changed(emitter: &SignalEmitter<'_>, uid: u32) -> zbus::Result<()>;
+
+    /// Returns the RON serialized [`UserData`] of a user, without wallpaper data
+    async fn get_user_data(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        uid: u32,
+    ) -> Result<String, GreeterError> {
+        let user = worker::run(move || user(uid)).await?;
 
+        let mut user_data = user_data(connection, &user)
+            .await
+            .ok_or(GreeterError::UnknownUser(uid))?;
+
+        let user_data = worker::run(move || {
             //IMPORTANT: Assume the identity of the user to ensure we don't read user file data as root
             run_as_user(&user, || user_data.load_config_as_user())
-                .map_err(|err| GreeterError::RunAsUser(err.to_string()))?;
+                .map(|()| user_data)
+                .map_err(|err| GreeterError::RunAsUser(err.to_string()))
+        })
+        .await?;
+
+        ron::to_string(&user_data).map_err(|err| GreeterError::Ron(err.to_string()))
+    }
 
-            user_datas.push(user_data);
+    /// Returns a sealed memfd with the wallpaper image data of a user for an output, scaled down
+    /// to the size of the output if possible
+    async fn get_wallpaper(
+        &self,
+        #[zbus(connection)] connection: &zbus::Connection,
+        uid: u32,
+        output: &str,
+    ) -> Result<OwnedFd, GreeterError> {
+        let user = worker::run(move || user(uid)).await?;
+        // Hidden users are not accessible, like in list_users and get_user_data
+        if user_data(connection, &user).await.is_none() {
+            return Err(GreeterError::UnknownUser(uid));
         }
 
-        //TODO: is ron the best choice for passing around background data?
-        ron::to_string(&user_datas).map_err(|err| GreeterError::Ron(err.to_string()))
+        let output = output.to_string();
+        let runtime = tokio::runtime::Handle::current();
+        let (mut wallpaper, child) =
+            match worker::run(move || open_wallpaper(&user, &output, &runtime)).await? {
+                OpenWallpaper::Ready(fd) => return Ok(fd),
+                OpenWallpaper::Scale(wallpaper, child) => (wallpaper, child),
+            };
+
+        let data_opt = match tokio::time::timeout(SCALE_TIMEOUT, child.wait_with_output()).await {
+            Ok(Ok(output)) if output.status.success() => {
+                Some(output.stdout).filter(|data| !data.is_empty())
+            }
+            Ok(Ok(output)) => {
+                warn!(
+                    "failed to scale wallpaper {:?}: {}: {}",
+                    wallpaper.path,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+                None
+            }
+            Ok(Err(err)) => {
+                warn!("failed to scale wallpaper {:?}: {}", wallpaper.path, err);
+                None
+            }
+            Err(_elapsed) => {
+                // The child is killed when dropped
+                warn!("timed out scaling wallpaper {:?}", wallpaper.path);
+                None
+            }
+        };
+
+        worker::run(move || {
+            let memfd_res = match data_opt {
+                Some(data) => {
+                    if let Err(err) = wallpaper.cache.store(&wallpaper.key, &data) {
+                        warn!("failed to cache wallpaper {:?}: {}", wallpaper.path, err);
+                    }
+                    sealed_memfd(&mut data.as_slice())
+                }
+                None => wallpaper
+                    .file
+                    .rewind()
+                    .and_then(|()| sealed_memfd(&mut wallpaper.file)),
+            };
+            memfd_res.map(OwnedFd::from).map_err(memfd_error)
+        })
+        .await
     }
 }
 
+/// Scaling is stopped after this long and the original image is used
+const SCALE_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn memfd_error(err: io::Error) -> GreeterError {
+    GreeterError::Wallpaper(format!("failed to create memfd: {}", err))
+}
+
+/// A wallpaper that is being scaled by a child process
+struct ScaledWallpaper {
+    path: PathBuf,
+    file: fs::File,
+    cache: wallpaper::Cache,
+    key: wallpaper::CacheKey,
+}
+
+enum OpenWallpaper {
+    Ready(OwnedFd),
+    Scale(ScaledWallpaper, tokio::process::Child),
+}
+
+/// Opens the wallpaper of a user for an output from the cache, or starts scaling it. Must be
+/// called on the worker thread, see [`worker::run`].
+fn open_wallpaper(
+    user: &pwd::Passwd,
+    output: &str,
+    runtime: &tokio::runtime::Handle,
+) -> Result<OpenWallpaper, GreeterError> {
+    let (path, mut file, size_opt, scaling_mode) = {
+        let mut user_data = UserData::from(user.clone());
+        //IMPORTANT: Assume the identity of the user to ensure we don't read user file data as root
+        run_as_user(user, || {
+            user_data.load_bg_config_as_user();
+            user_data.load_bg_state_as_user();
+            user_data.load_output_lists_as_user();
+            let size_opt = wallpaper::output_sizes(&user_data.kdl_output_lists)
+                .get(output)
+                .copied();
+            let scaling_mode = user_data.bg_scaling_mode(output);
+            match user_data.bg_source(output) {
+                Some(BgSource::Path(path)) => {
+                    let path = if path.is_dir() {
+                        wallpaper::first_image(path).map_err(|err| {
+                            GreeterError::Wallpaper(format!(
+                                "failed to find image in {:?}: {}",
+                                path, err
+                            ))
+                        })?
+                    } else {
+                        path.clone()
+                    };
+                    match fs::File::open(&path) {
+                        Ok(file) => Ok((path, file, size_opt, scaling_mode)),
+                        Err(err) => Err(GreeterError::Wallpaper(format!(
+                            "failed to open {:?}: {}",
+                            path, err
+                        ))),
+                    }
+                }
+                source => Err(GreeterError::Wallpaper(format!(
+                    "no wallpaper image for output {output}: {source:?}"
+                ))),
+            }
+        })
+        .map_err(|err| GreeterError::RunAsUser(err.to_string()))??
+    };
+
+    if let Some(size) = size_opt
+        && let Some(cache) = wallpaper::Cache::new(user.uid, output)
+    {
+        match file.metadata().and_then(|metadata| {
+            wallpaper::CacheKey::new(&path, &metadata, size, scaling_mode.clone())
+        }) {
+            Ok(key) => {
+                if let Some(mut cached) = cache.load(&key) {
+                    return sealed_memfd(&mut cached)
+                        .map(|fd| OpenWallpaper::Ready(fd.into()))
+                        .map_err(memfd_error);
+                }
+
+                //IMPORTANT: images are decoded by a child process running as the user, the
+                // decoders must never see user controlled data as root
+                let child_res = file.try_clone().and_then(|input| {
+                    let _guard = runtime.enter();
+                    tokio::process::Command::new(env::current_exe()?)
+                        .arg(wallpaper::SCALE_ARG)
+                        .arg(size.0.to_string())
+                        .arg(size.1.to_string())
+                        .arg(ron::to_string(&scaling_mode).map_err(io::Error::other)?)
+                        .env_clear()
+                        .uid(user.uid)
+                        .gid(user.gid)
+                        .stdin(Stdio::from(input))
+                        .stdout(Stdio::piped())
+                        .stderr(Stdio::piped())
+                        .kill_on_drop(true)
+                        .spawn()
+                });
+                match child_res {
+                    Ok(child) => {
+                        return Ok(OpenWallpaper::Scale(
+                            ScaledWallpaper {
+                                path,
+                                file,
+                                cache,
+                                key,
+                            },
+                            child,
+                        ));
+                    }
+                    Err(err) => {
+                        warn!("failed to start scaling wallpaper {:?}: {}", path, err);
+                    }
+                }
+            }
+            Err(err) => {
+                warn!("failed to read metadata of wallpaper {:?}: {}", path, err);
+            }
+        }
+    }
+
+    sealed_memfd(&mut file)
+        .map(|fd| OpenWallpaper::Ready(fd.into()))
+        .map_err(memfd_error)
+}
+
+/// Scales the image read from stdin and writes it to stdout as PNG, writing nothing when the
+/// image does not need to be scaled. Runs in a child process as the user, see [`open_wallpaper`].
+fn scale_wallpaper(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [width, height, scaling_mode] = args else {
+        return Err(format!("usage: {} WIDTH HEIGHT SCALING_MODE", wallpaper::SCALE_ARG).into());
+    };
+    let size = (width.parse()?, height.parse()?);
+    let scaling_mode: BgScalingMode = ron::from_str(scaling_mode)?;
+
+    // Allow scaling JPEG XL wallpapers
+    let _ = jxl_oxide::integration::register_image_decoding_hook();
+
+    // The wallpaper file is passed as stdin, so it can be read and rewound like a file
+    let file = fs::File::from(io::stdin().as_fd().try_clone_to_owned()?);
+    if let Some(data) = wallpaper::scale(io::BufReader::new(file), size, &scaling_mode)? {
+        io::stdout().write_all(&data)?;
+    }
+    Ok(())
+}
+
+/// Copies data into a memfd that is sealed against modification, so it can be shared safely
+fn sealed_memfd<R: Read>(reader: &mut R) -> io::Result<std::os::fd::OwnedFd> {
+    use nix::fcntl::{FcntlArg, SealFlag, fcntl};
+    use nix::sys::memfd::{MFdFlags, memfd_create};
+
+    let fd = memfd_create(
+        c"cosmic-greeter-wallpaper",
+        MFdFlags::MFD_CLOEXEC | MFdFlags::MFD_ALLOW_SEALING,
+    )?;
+    let mut file = fs::File::from(fd);
+    io::copy(reader, &mut file)?;
+    file.rewind()?;
+
+    let fd = std::os::fd::OwnedFd::from(file);
+    fcntl(
+        &fd,
+        FcntlArg::F_ADD_SEALS(
+            SealFlag::F_SEAL_SHRINK
+                | SealFlag::F_SEAL_GROW
+                | SealFlag::F_SEAL_WRITE
+                | SealFlag::F_SEAL_SEAL,
+        ),
+    )?;
+    Ok(fd)
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some(wallpaper::SCALE_ARG) {
+        return scale_wallpaper(&args[1..]);
+    }
+
     color_eyre::install().wrap_err("failed to install color_eyre error handler")?;
 
     let trace = tracing_subscriber::registry();
@@ -129,12 +440,18 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .try_init()
         .wrap_err("failed to initialize logger")?;
 
-    let _conn = Builder::system()?
+    let conn = Builder::system()?
         .name("com.system76.CosmicGreeter")?
         .serve_at("/com/system76/CosmicGreeter", GreeterProxy)?
         .build()
         .await?;
 
+    tokio::spawn(async move {
+        if let Err(err) = watch::watch(conn).await {
+            tracing::error!("failed to watch user data: {}", err);
+        }
+    });
+
     pending::<()>().await;
 
     Ok(())
@@ -0,0 +1,231 @@
+use crate::BgScalingMode;
+use cosmic_randr_shell::{KdlParseWithError, List};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader, ImageResult};
+use kdl::KdlDocument;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Cursor, Seek, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+/// Scaled wallpapers are cached here, this directory must only be accessible by root
+pub const CACHE_DIR: &str = "/var/cache/cosmic-greeter";
+
+/// Argument of the daemon that runs [`scale`] in a child process
+pub const SCALE_ARG: &str = "scale-wallpaper";
+
+/// Makes temporary file names unique within the daemon
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Writes a file through a temporary file with a unique name, so that it is replaced atomically
+/// and concurrent writes of the same file do not interleave
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_owned();
+    tmp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp_path = path.with_file_name(tmp_name);
+    let res = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp_path, path));
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    res
+}
+
+/// Finds the largest configured mode of each output in the user's output lists
+pub fn output_sizes(kdl_output_lists: &[String]) -> HashMap<String, (u32, u32)> {
+    let mut sizes = HashMap::<String, (u32, u32)>::new();
+    for list in kdl_output_lists
+        .iter()
+        .filter_map(|s| match KdlDocument::parse(s) {
+            Ok(doc) => Some(doc),
+            Err(err) => {
+                tracing::warn!("invalid output KDL: {:?}", err);
+                None
+            }
+        })
+        .map(|kdl| match List::try_from(kdl) {
+            Ok(list) => list,
+            Err(KdlParseWithError { list, .. }) => list,
+        })
+    {
+        for output in list.outputs.values() {
+            //TODO: account for output transform
+            let Some(mode) = output.current.and_then(|key| list.modes.get(key)) else {
+                continue;
+            };
+            let size = sizes.entry(output.name.clone()).or_default();
+            if u64::from(mode.size.0) * u64::from(mode.size.1)
+                > u64::from(size.0) * u64::from(size.1)
+            {
+                *size = mode.size;
+            }
+        }
+    }
+    sizes
+}
+
+/// Identifies the source of a cached wallpaper, the cache is invalidated when any of this changes
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct CacheKey {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+    pub len: u64,
+    pub size: (u32, u32),
+    pub scaling_mode: BgScalingMode,
+}
+
+impl CacheKey {
+    pub fn new(
+        path: &Path,
+        metadata: &fs::Metadata,
+        size: (u32, u32),
+        scaling_mode: BgScalingMode,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_owned(),
+            modified: metadata.modified()?,
+            len: metadata.len(),
+            size,
+            scaling_mode,
+        })
+    }
+}
+
+/// Finds the first file in a wallpaper directory, used when cosmic-bg has not picked one yet
+pub fn first_image(dir: &Path) -> io::Result<PathBuf> {
+    let mut paths = Vec::new();
+    for entry_res in fs::read_dir(dir)? {
+        let entry = entry_res?;
+        if entry.file_type()?.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+    paths
+        .into_iter()
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no files in directory"))
+}
+
+/// Cache of scaled wallpapers, with one entry per user and output
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// Returns `None` if the output name can not be used as a file name
+    pub fn new(uid: u32, output: &str) -> Option<Self> {
+        //IMPORTANT: the output name is provided by the caller and must not escape the cache
+        if output.is_empty()
+            || !output
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return None;
+        }
+        Some(Self {
+            dir: Path::new(CACHE_DIR).join(uid.to_string()).join(output),
+        })
+    }
+
+    /// Opens the cached image if it was created from the same source
+    pub fn load(&self, key: &CacheKey) -> Option<fs::File> {
+        let cached_key: CacheKey =
+            ron::from_str(&fs::read_to_string(self.dir.join("key.ron")).ok()?).ok()?;
+        if &cached_key != key {
+            return None;
+        }
+        fs::File::open(self.dir.join("wallpaper.png")).ok()
+    }
+
+    /// Replaces the cached image
+    pub fn store(&self, key: &CacheKey, data: &[u8]) -> io::Result<()> {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&self.dir)?;
+
+        // Remove the old key first, so an interrupted update is never seen as valid
+        match fs::remove_file(self.dir.join("key.ron")) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        // Replace the image atomically, it may be read by another call at the same time
+        write_atomic(&self.dir.join("wallpaper.png"), data)?;
+
+        let key_ron = ron::to_string(key).map_err(io::Error::other)?;
+        write_atomic(&self.dir.join("key.ron"), key_ron.as_bytes())
+    }
+}
+
+/// Scales an image down to the size it will be shown at on an output of the given size,
+/// returning PNG data. Returns `None` if the image is not larger than needed.
+pub fn scale<R: BufRead + Seek>(
+    mut reader: R,
+    size: (u32, u32),
+    scaling_mode: &BgScalingMode,
+) -> ImageResult<Option<Vec<u8>>> {
+    // Read only the header first, so small images are not decoded
+    let (width, height) = ImageReader::new(&mut reader)
+        .with_guessed_format()?
+        .into_dimensions()?;
+    if width == 0 || height == 0 || size.0 == 0 || size.1 == 0 {
+        return Ok(None);
+    }
+    let (scaled_width, scaled_height) = match scaling_mode {
+        BgScalingMode::Stretch => (width.min(size.0), height.min(size.1)),
+        BgScalingMode::Fit(_) | BgScalingMode::Zoom => {
+            let width_factor = f64::from(size.0) / f64::from(width);
+            let height_factor = f64::from(size.1) / f64::from(height);
+            let factor = if matches!(scaling_mode, BgScalingMode::Zoom) {
+                // Cover the output
+                width_factor.max(height_factor)
+            } else {
+                // Fit inside the output
+                width_factor.min(height_factor)
+            };
+            if factor >= 1.0 {
+                return Ok(None);
+            }
+            (
+                (f64::from(width) * factor).ceil() as u32,
+                (f64::from(height) * factor).ceil() as u32,
+            )
+        }
+    };
+    if (scaled_width, scaled_height) == (width, height) {
+        return Ok(None);
+    }
+
+    reader.rewind()?;
+    let image = ImageReader::new(reader).with_guessed_format()?.decode()?;
+    let scaled = image.resize_exact(scaled_width, scaled_height, FilterType::Lanczos3);
+
+    // PNG does not support all color types, and 8 bits per channel are enough for display
+    let scaled = if scaled.color().has_alpha() {
+        DynamicImage::ImageRgba8(scaled.into_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(scaled.into_rgb8())
+    };
+    let mut data = Cursor::new(Vec::new());
+    scaled.write_to(&mut data, ImageFormat::Png)?;
+    Ok(Some(data.into_inner()))
+}
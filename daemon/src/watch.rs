@@ -0,0 +1,162 @@
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+use std::collections::{HashMap, HashSet};
+use std::os::fd::{AsFd, AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{fs, io};
+use tokio::io::unix::AsyncFd;
+use zbus::object_server::SignalEmitter;
+
+use crate::{GreeterProxy, run_as_user, users, worker};
+
+/// Configuration read by [`cosmic_greeter_daemon::UserData::load_config_as_user`]
+const CONFIG_NAMES: &[&str] = &[
+    "com.system76.CosmicAppletTime",
+    "com.system76.CosmicBackground",
+    "com.system76.CosmicComp",
+    "com.system76.CosmicTheme.Dark",
+    "com.system76.CosmicTheme.Dark.Builder",
+    "com.system76.CosmicTheme.Light",
+    "com.system76.CosmicTheme.Light.Builder",
+    "com.system76.CosmicTheme.Mode",
+];
+
+/// State read by [`cosmic_greeter_daemon::UserData::load_config_as_user`]
+const STATE_NAMES: &[&str] = &["com.system76.CosmicBackground"];
+
+const ICON_DIR: &str = "/var/lib/AccountsService/icons";
+
+/// Changes are often written as several files, so wait for more before signalling them
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+// Inotify only implements AsFd, which is not enough for AsyncFd
+struct InotifyFd(Inotify);
+
+impl AsRawFd for InotifyFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_fd().as_raw_fd()
+    }
+}
+
+/// Finds directories containing user configuration, must be called as the user
+fn user_dirs_as_user() -> Vec<PathBuf> {
+    let xdg = xdg::BaseDirectories::new();
+    let mut parents = Vec::new();
+    if let Some(config_home) = xdg.get_config_home() {
+        for name in CONFIG_NAMES {
+            parents.push(config_home.join("cosmic").join(name));
+        }
+    }
+    if let Some(state_home) = xdg.get_state_home() {
+        for name in STATE_NAMES {
+            parents.push(state_home.join("cosmic").join(name));
+        }
+        parents.push(state_home.join("cosmic-comp"));
+    }
+
+    // Configuration is stored in a subdirectory per version
+    let mut dirs = Vec::new();
+    for parent in parents {
+        //TODO: watch directories that are created later
+        let Ok(entries) = fs::read_dir(&parent) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                dirs.push(entry.path());
+            }
+        }
+        dirs.push(parent);
+    }
+    dirs
+}
+
+/// Watches user configuration and account icons, emitting `UserDataChanged` when they change
+pub async fn watch(connection: zbus::Connection) -> io::Result<()> {
+    let inotify = Inotify::init(InitFlags::IN_CLOEXEC | InitFlags::IN_NONBLOCK)?;
+    let flags = AddWatchFlags::IN_CLOSE_WRITE
+        | AddWatchFlags::IN_MOVED_TO
+        | AddWatchFlags::IN_CREATE
+        | AddWatchFlags::IN_DELETE;
+
+    //TODO: watch users that are added later
+    let (inotify, user_watches, icon_names, icon_wd_opt) = worker::run(move || {
+        let mut user_watches = HashMap::<WatchDescriptor, u32>::new();
+        let mut icon_names = HashMap::<String, u32>::new();
+        for user in users() {
+            icon_names.insert(user.name.clone(), user.uid);
+            //IMPORTANT: Assume the identity of the user to ensure we only watch what the user can read
+            let res = run_as_user(&user, || {
+                for dir in user_dirs_as_user() {
+                    match inotify.add_watch(dir.as_path(), flags) {
+                        Ok(wd) => {
+                            user_watches.insert(wd, user.uid);
+                        }
+                        Err(err) => {
+                            tracing::warn!("failed to watch {:?}: {}", dir, err);
+                        }
+                    }
+                }
+            });
+            if let Err(err) = res {
+                tracing::warn!("failed to watch config of {}: {}", user.name, err);
+            }
+        }
+        let icon_wd_opt = match inotify.add_watch(Path::new(ICON_DIR), flags) {
+            Ok(wd) => Some(wd),
+            Err(err) => {
+                tracing::warn!("failed to watch {:?}: {}", ICON_DIR, err);
+                None
+            }
+        };
+        (inotify, user_watches, icon_names, icon_wd_opt)
+    })
+    .await;
+
+    let emitter =
+        SignalEmitter::new(&connection, "/com/system76/CosmicGreeter").map_err(io::Error::other)?;
+    let fd = AsyncFd::new(InotifyFd(inotify))?;
+    loop {
+        let mut changed = HashSet::new();
+        fd.readable().await?.clear_ready();
+        loop {
+            let events = match fd.get_ref().0.read_events() {
+                Ok(events) => events,
+                Err(nix::errno::Errno::EAGAIN) => {
+                    if changed.is_empty() {
+                        break;
+                    }
+                    // Collect more events before signalling
+                    match tokio::time::timeout(DEBOUNCE, fd.readable()).await {
+                        Ok(guard_res) => {
+                            guard_res?.clear_ready();
+                            continue;
+                        }
+                        Err(_elapsed) => break,
+                    }
+                }
+                Err(err) => return Err(err.into()),
+            };
+            for event in events {
+                if Some(event.wd) == icon_wd_opt {
+                    if let Some(uid) = event
+                        .name
+                        .as_ref()
+                        .and_then(|name| icon_names.get(name.to_str()?))
+                    {
+                        changed.insert(*uid);
+                    }
+                } else if let Some(uid) = user_watches.get(&event.wd) {
+                    changed.insert(*uid);
+                }
+            }
+        }
+
+        for uid in changed {
+            tracing::info!("user data of {} changed", uid);
+            if let Err(err) = GreeterProxy::user_data_changed(&emitter, uid).await {
+                tracing::warn!("failed to emit user data change of {}: {}", uid, err);
+            }
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::sync::LazyLock;
+use std::sync::mpsc;
+use std::{panic, process, thread};
+use tokio::sync::oneshot;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+//IMPORTANT: seteuid and setegid change the identity of every thread of the process. All work
+// that reads or writes files, as root or as a user, must run on this one thread so that it can
+// never happen while another request has switched to a different user.
+static WORKER: LazyLock<mpsc::Sender<Job>> = LazyLock::new(|| {
+    let (sender, receiver) = mpsc::channel::<Job>();
+    thread::Builder::new()
+        .name("cosmic-greeter-daemon-worker".to_string())
+        .spawn(move || {
+            for job in receiver {
+                if panic::catch_unwind(panic::AssertUnwindSafe(job)).is_err() {
+                    // The identity of the process may not have been restored
+                    tracing::error!("worker job panicked, exiting");
+                    process::abort();
+                }
+            }
+        })
+        .expect("failed to spawn worker thread");
+    sender
+});
+
+/// Runs blocking work on the worker thread, one job at a time, without blocking the executor
+pub async fn run<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    WORKER
+        .send(Box::new(move || {
+            let _ = sender.send(f());
+        }))
+        .expect("worker thread exited");
+    // The worker aborts the process instead of dropping a job
+    receiver.await.expect("worker job was dropped")
+}
@@ -1,9 +1,160 @@
+//! Mock greetd server, which runs the greeter against a scripted conversation.
+//!
+//! Usage: `just mock examples/scenarios/two-factor.ron`, which passes the scenario to the
+//! server in `MOCK_SCENARIO`.
+//!
+//! Without a scenario, the greeter asks for a password and accepts "password".
+
 use greetd_ipc::codec::TokioCodec;
 use greetd_ipc::{AuthMessageType, ErrorType, Request, Response};
+use serde::Deserialize;
+use std::time::Duration;
 use std::{env, fs, io, thread};
 use tokio::net::UnixListener;
 
+#[derive(Clone, Copy, Debug, Deserialize)]
+enum Kind {
+    Secret,
+    Visible,
+    Info,
+    Error,
+}
+
+impl From<Kind> for AuthMessageType {
+    fn from(kind: Kind) -> Self {
+        match kind {
+            Kind::Secret => Self::Secret,
+            Kind::Visible => Self::Visible,
+            Kind::Info => Self::Info,
+            Kind::Error => Self::Error,
+        }
+    }
+}
+
+/// One message of the conversation started by `CreateSession`
+#[derive(Clone, Debug, Deserialize)]
+struct Step {
+    kind: Kind,
+    message: String,
+    /// Response that is accepted, any response is accepted if not set
+    #[serde(default)]
+    expect: Option<String>,
+    /// Time PAM takes before sending this message
+    #[serde(default)]
+    delay_ms: u64,
+    /// Fail with this PAM error, like "PERM_DENIED", instead of sending the message
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct Scenario {
+    /// Messages of the conversation, an empty list logs in without authentication
+    steps: Vec<Step>,
+    /// Time PAM takes to reject a wrong response
+    #[serde(default = "default_failure_delay_ms")]
+    failure_delay_ms: u64,
+    /// Fail `StartSession` with this description
+    #[serde(default)]
+    start_session_error: Option<String>,
+}
+
+fn default_failure_delay_ms() -> u64 {
+    // Simulate real PAM authentication failure behavior
+    1000
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            steps: vec![Step {
+                kind: Kind::Secret,
+                message: "MOCKING:".to_string(),
+                expect: Some("password".to_string()),
+                delay_ms: 0,
+                error: None,
+            }],
+            failure_delay_ms: default_failure_delay_ms(),
+            start_session_error: None,
+        }
+    }
+}
+
+impl Scenario {
+    fn load() -> Self {
+        let Some(path) = env::var_os("MOCK_SCENARIO").filter(|path| !path.is_empty()) else {
+            return Self::default();
+        };
+        let ron = fs::read_to_string(&path)
+            .unwrap_or_else(|err| panic!("failed to read scenario {:?}: {}", path, err));
+        ron::from_str(&ron)
+            .unwrap_or_else(|err| panic!("failed to parse scenario {:?}: {}", path, err))
+    }
+
+    /// Answers with the step at `index`, or success when the conversation is complete
+    async fn step(&self, index: &mut Option<usize>) -> Response {
+        let Some(step) = index.and_then(|i| self.steps.get(i)) else {
+            *index = None;
+            return Response::Success;
+        };
+        tokio::time::sleep(Duration::from_millis(step.delay_ms)).await;
+        match &step.error {
+            Some(description) => {
+                *index = None;
+                Response::Error {
+                    error_type: ErrorType::AuthError,
+                    description: description.clone(),
+                }
+            }
+            None => Response::AuthMessage {
+                auth_message_type: step.kind.into(),
+                auth_message: step.message.clone(),
+            },
+        }
+    }
+
+    async fn respond(&self, index: &mut Option<usize>, request: Request) -> Response {
+        match request {
+            Request::CreateSession { .. } => {
+                *index = Some(0);
+                self.step(index).await
+            }
+            Request::PostAuthMessageResponse { response } => {
+                let Some(step) = index.and_then(|i| self.steps.get(i)) else {
+                    return Response::Error {
+                        error_type: ErrorType::Error,
+                        description: "no conversation in progress".to_string(),
+                    };
+                };
+                if step.expect.is_some() && step.expect != response {
+                    *index = None;
+                    tokio::time::sleep(Duration::from_millis(self.failure_delay_ms)).await;
+                    return Response::Error {
+                        error_type: ErrorType::AuthError,
+                        description: "AUTH_ERR".to_string(),
+                    };
+                }
+                *index = index.map(|i| i + 1);
+                self.step(index).await
+            }
+            Request::StartSession { .. } => match &self.start_session_error {
+                Some(description) => Response::Error {
+                    error_type: ErrorType::Error,
+                    description: description.clone(),
+                },
+                None => Response::Success,
+            },
+            Request::CancelSession => {
+                *index = None;
+                Response::Success
+            }
+        }
+    }
+}
+
 fn main() {
+    let scenario = Scenario::load();
+
     let greetd_sock = env::current_dir().unwrap().join("socket");
     if greetd_sock.exists() {
         fs::remove_file(&greetd_sock).unwrap();
@@ -22,6 +173,7 @@ fn main() {
             loop {
                 let (socket, _addr) = listener.accept().await.unwrap();
                 println!("new connection");
+                let mut index = None;
 
                 loop {
                     let request = {
@@ -47,27 +199,8 @@ fn main() {
                     };
                     println!("{:?}", request);
 
-                    let response = match request {
-                        Request::CreateSession { .. } => Response::AuthMessage {
-                            auth_message_type: AuthMessageType::Secret,
-                            auth_message: "MOCKING:".to_string(),
-                        },
-                        Request::PostAuthMessageResponse { response } => {
-                            match response.as_deref() {
-                                Some("password") => Response::Success,
-                                _ => {
-                                    // Add 1 second delay to simulate real PAM authentication failure behavior
-                                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-                                    Response::Error {
-                                        error_type: ErrorType::AuthError,
-                                        description: "AUTH_ERR".to_string(),
-                                    }
-                                }
-                            }
-                        }
-                        Request::StartSession { .. } => Response::Success,
-                        Request::CancelSession => Response::Success,
-                    };
+                    let response = scenario.respond(&mut index, request).await;
+                    println!("{:?}", response);
 
                     let mut bytes = Vec::with_capacity(4096);
                     response.write_to(&mut bytes).await.unwrap();
@@ -6,6 +6,7 @@ accessibility = Accessibility
 authenticating = Authenticating...
 cancel = Cancel
 caps-lock = Caps Lock is active.
+prompt-step = Step {$step}
 enter-user = Enter name manually...
 type-username = Username:
 keyboard-layout = Keyboard layout
@@ -18,6 +19,18 @@ restart-timeout = The system will restart automatically
     *[other] in {$seconds} seconds.
   }
 session = Session
+session-unavailable = Your last session { $session } is no longer installed, { $fallback } will be used instead
+session-none = No session is available to log in to
+session-x11 = { $name } (X11)
+session-crashed = Your last session ended unexpectedly
+session-crashed-retry = Retry
+session-crashed-choose = Choose another session
+autologin-countdown = { $seconds ->
+    [1] Logging in automatically in 1 second
+    *[other] Logging in automatically in {$seconds} seconds
+  }
+autologin-cancel = Press any key to cancel
+autologin-now = Log in now
 shutdown = Shut down
 shutdown-now = Shut down now?
 shutdown-timeout = The system will shut down automatically
@@ -29,9 +42,42 @@ shutdown-timeout = The system will shut down automatically
 suspend = Suspend
 user = User
 
+# Connection to greetd
+greetd-retrying = Login service unavailable, retrying...
+greetd-not-set = Login service not found
+greetd-not-set-description = The greeter must be started by greetd, which sets the GREETD_SOCK variable.
+
 # Authentication errors
 auth-error-default = Authentication failed. Please try again.
 auth-error-credentials = Incorrect password. Please check your keyboard layout and try again.
 auth-error-denied = Access denied.
 auth-error-maxtries = Too many failed authentication attempts.
 auth-error-account = Account is unavailable or disabled.
+auth-error-unavailable = Authentication is currently unavailable.
+auth-error-conversation = Authentication was interrupted. Please try again.
+failed-attempts = { $attempts ->
+    [1] 1 failed attempt.
+    *[other] {$attempts} failed attempts.
+  }
+lockout-countdown = Too many failed attempts. Try again in {$minutes}:{$seconds}.
+
+# Expired password change
+password-expired = Your password has expired. Please choose a new password.
+password-current = Current password
+password-new = New password
+password-confirm = Confirm new password
+password-mismatch = The new passwords do not match. Please try again.
+password-quality = The new password was rejected: {$reason}
+password-change-failed = The password could not be changed.
+
+# Fingerprint and security key indicator
+fingerprint-waiting = Touch the fingerprint reader to unlock.
+fingerprint-matched = Fingerprint recognized.
+fingerprint-failed = Fingerprint not recognized. Please try again.
+security-key-waiting = Touch your security key to unlock.
+security-key-matched = Security key recognized.
+security-key-failed = Security key not recognized. Please try again.
+biometric-retries = { $retries ->
+    [1] 1 try left.
+    *[other] {$retries} tries left.
+  }
@@ -6,18 +6,22 @@ use cosmic::iced::keyboard::{Event as KeyEvent, Key, Modifiers};
 use cosmic::iced::platform_specific::shell::commands::blur::blur;
 use cosmic::iced::runtime::core::window::Id as SurfaceId;
 use cosmic::iced::runtime::platform_specific::wayland::CornerRadius;
-use cosmic::iced::{self, Rectangle, Size, Subscription};
+use cosmic::iced::{self, Length, Rectangle, Size, Subscription};
 use cosmic::surface::corner_radius::rounded_rect_strips;
 use cosmic::widget::rectangle_tracker::{RectangleUpdate, rectangle_tracker_subscription};
 use cosmic::widget::{self, RectangleTracker};
-use cosmic_greeter_daemon::{BgSource, UserData};
+use cosmic::{Element, theme};
+use cosmic_greeter_daemon::{BgScalingMode, BgSource, Color as BgColor, UserData};
 use cosmic_protocols::keyboard_layout::v1::client::zcosmic_keyboard_layout_v1::ZcosmicKeyboardLayoutV1;
 use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use wayland_client::protocol::wl_output::WlOutput;
 use wayland_client::{Connection, Proxy};
 
-use crate::keyboard_layout_wayland;
+use crate::{fl, keyboard_layout_wayland};
 
 pub const DEFAULT_MENU_ITEM_HEIGHT: f32 = 36.;
 
@@ -28,6 +32,323 @@ pub struct ActiveLayout {
     pub variant: String,
 }
 
+/// Background of an output surface
+#[derive(Clone, Debug)]
+pub enum Background {
+    Image(widget::image::Handle, BgScalingMode),
+    Color(BgColor),
+}
+
+impl Background {
+    /// Draws the background, images are drawn with the given opacity
+    pub fn view<'a, T: 'a>(&'a self, opacity: f32) -> Element<'a, T> {
+        let (image, scaling_mode) = match self {
+            Self::Image(image, scaling_mode) => (image, scaling_mode),
+            Self::Color(color) => {
+                return fill_background(widget::space::horizontal(), color_background(color));
+            }
+        };
+        let image = widget::image(image)
+            .opacity(opacity)
+            .width(Length::Fill)
+            .height(Length::Fill);
+        match scaling_mode {
+            BgScalingMode::Zoom => image.content_fit(iced::ContentFit::Cover).into(),
+            BgScalingMode::Stretch => image.content_fit(iced::ContentFit::Fill).into(),
+            BgScalingMode::Fit([r, g, b]) => fill_background(
+                image.content_fit(iced::ContentFit::Contain),
+                iced::Background::Color(iced::Color::from_rgb(*r, *g, *b)),
+            ),
+        }
+    }
+}
+
+/// Creates an image for wallpaper data, blurring it if requested. Returns `None` if the
+/// wallpaper could not be blurred, so it is never shown without blur.
+pub async fn wallpaper_image(bytes: Vec<u8>, blur_radius: f32) -> Option<widget::image::Handle> {
+    if blur_radius <= 0.0 {
+        return Some(widget::image::Handle::from_bytes(bytes));
+    }
+
+    let res = tokio::task::spawn_blocking(move || -> image::ImageResult<_> {
+        let image = image::ImageReader::new(io::Cursor::new(bytes))
+            .with_guessed_format()?
+            .decode()?;
+        Ok(image.fast_blur(blur_radius).into_rgba8())
+    })
+    .await;
+    match res {
+        Ok(Ok(blurred)) => Some(widget::image::Handle::from_rgba(
+            blurred.width(),
+            blurred.height(),
+            blurred.into_raw(),
+        )),
+        Ok(Err(err)) => {
+            tracing::error!("failed to blur wallpaper: {}", err);
+            None
+        }
+        Err(err) => {
+            tracing::error!("failed to run wallpaper blur task: {}", err);
+            None
+        }
+    }
+}
+
+/// Fills a surface with a background behind some content
+fn fill_background<'a, T: 'a>(
+    content: impl Into<Element<'a, T>>,
+    background: iced::Background,
+) -> Element<'a, T> {
+    widget::container(content)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .class(theme::Container::custom(move |_theme| {
+            widget::container::Style {
+                background: Some(background),
+                ..Default::default()
+            }
+        }))
+        .into()
+}
+
+/// Converts a cosmic-bg color source to an iced background
+fn color_background(color: &BgColor) -> iced::Background {
+    match color {
+        BgColor::Single([r, g, b]) => iced::Background::Color(iced::Color::from_rgb(*r, *g, *b)),
+        BgColor::Gradient(gradient) => {
+            // cosmic-bg uses the radius as the angle of a linear gradient, in degrees
+            let mut linear = iced::gradient::Linear::new(iced::Degrees(gradient.radius));
+            //TODO: iced only supports up to 8 color stops
+            let last = gradient.colors.len().saturating_sub(1).max(1) as f32;
+            for (i, [r, g, b]) in gradient.colors.iter().enumerate() {
+                linear = linear.add_stop(i as f32 / last, iced::Color::from_rgb(*r, *g, *b));
+            }
+            iced::Background::Gradient(linear.into())
+        }
+    }
+}
+
+/// Type of a message received from PAM during authentication
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PromptKind {
+    /// Input that is hidden while typing, like a password
+    Secret,
+    /// Input that is shown while typing, like a one-time code
+    Visible,
+    /// Text that does not need an answer
+    Info,
+    /// Error that does not end the conversation, like a rejected new password
+    Error,
+}
+
+impl PromptKind {
+    /// Returns true if the prompt needs an answer from the user
+    pub fn is_input(self) -> bool {
+        matches!(self, Self::Secret | Self::Visible)
+    }
+}
+
+/// Field of the password change conversation that a prompt asks for
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PasswordField {
+    Current,
+    New,
+    Confirm,
+}
+
+impl PasswordField {
+    /// Recognises the prompts of pam_unix and pam_pwquality
+    pub fn from_prompt(prompt: &str) -> Option<Self> {
+        // Only English prompts are recognised, see `is_password_expired_message`
+        let lower = prompt.to_lowercase();
+        if !lower.contains("password") {
+            None
+        } else if ["retype", "re-enter", "repeat", "confirm", "again"]
+            .iter()
+            .any(|word| lower.contains(word))
+        {
+            Some(Self::Confirm)
+        } else if lower.contains("new") {
+            Some(Self::New)
+        } else if lower.contains("current") || lower.contains("old") {
+            Some(Self::Current)
+        } else {
+            None
+        }
+    }
+}
+
+/// Reason authentication failed, shared by the greeter and the locker so both show the same
+/// messages
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AuthError {
+    /// Wrong password or other credentials
+    Credentials,
+    /// Not allowed to log in, for example while locked out by pam_faillock
+    Denied,
+    /// Too many failed attempts in one conversation
+    MaxTries,
+    /// The password expired and has to be changed
+    Expired,
+    /// Changing an expired password failed
+    PasswordChange,
+    /// The account is unknown, expired or disabled
+    Account,
+    /// A PAM module or the authentication service is not available
+    ServiceUnavailable,
+    /// The conversation with PAM failed
+    Conversation,
+    /// Any other error
+    Other,
+}
+
+impl AuthError {
+    pub fn from_pam(code: pam_client::ErrorCode) -> Self {
+        use pam_client::ErrorCode;
+
+        // Use the structured error code instead of string matching for reliability
+        match code {
+            ErrorCode::AUTH_ERR | ErrorCode::CRED_INSUFFICIENT => Self::Credentials,
+            ErrorCode::PERM_DENIED => Self::Denied,
+            ErrorCode::MAXTRIES => Self::MaxTries,
+            ErrorCode::NEW_AUTHTOK_REQD => Self::Expired,
+            ErrorCode::AUTHTOK_ERR
+            | ErrorCode::AUTHTOK_RECOVERY_ERR
+            | ErrorCode::AUTHTOK_LOCK_BUSY
+            | ErrorCode::AUTHTOK_DISABLE_AGING
+            | ErrorCode::TRY_AGAIN => Self::PasswordChange,
+            ErrorCode::ACCT_EXPIRED | ErrorCode::USER_UNKNOWN => Self::Account,
+            ErrorCode::AUTHINFO_UNAVAIL
+            | ErrorCode::SERVICE_ERR
+            | ErrorCode::SYSTEM_ERR
+            | ErrorCode::OPEN_ERR
+            | ErrorCode::SYMBOL_ERR
+            | ErrorCode::MODULE_UNKNOWN => Self::ServiceUnavailable,
+            ErrorCode::CONV_ERR | ErrorCode::CONV_AGAIN => Self::Conversation,
+            _ => Self::Other,
+        }
+    }
+
+    /// greetd only provides the error as text, which contains the name of the PAM error code
+    pub fn from_greetd(error_type: &greetd_ipc::ErrorType, description: &str) -> Self {
+        match error_type {
+            greetd_ipc::ErrorType::AuthError => {
+                if description.contains("PERM_DENIED") {
+                    Self::Denied
+                } else if description.contains("MAXTRIES") {
+                    Self::MaxTries
+                } else if description.contains("NEW_AUTHTOK_REQD") {
+                    Self::Expired
+                } else if description.contains("AUTHTOK") || description.contains("TRY_AGAIN") {
+                    Self::PasswordChange
+                } else if description.contains("ACCT_EXPIRED")
+                    || description.contains("USER_UNKNOWN")
+                {
+                    Self::Account
+                } else if description.contains("AUTHINFO_UNAVAIL")
+                    || description.contains("SERVICE_ERR")
+                    || description.contains("SYSTEM_ERR")
+                {
+                    Self::ServiceUnavailable
+                } else if description.contains("CONV_ERR") {
+                    Self::Conversation
+                } else {
+                    Self::Credentials
+                }
+            }
+            greetd_ipc::ErrorType::Error => Self::Other,
+        }
+    }
+
+    /// Localized message shown on the prompt
+    pub fn message(self) -> String {
+        match self {
+            Self::Credentials => fl!("auth-error-credentials"),
+            Self::Denied => fl!("auth-error-denied"),
+            Self::MaxTries => fl!("auth-error-maxtries"),
+            Self::Expired => fl!("password-expired"),
+            Self::PasswordChange => fl!("password-change-failed"),
+            Self::Account => fl!("auth-error-account"),
+            Self::ServiceUnavailable => fl!("auth-error-unavailable"),
+            Self::Conversation => fl!("auth-error-conversation"),
+            Self::Other => fl!("auth-error-default"),
+        }
+    }
+
+    /// Logs the error with its details, which are not shown to the user
+    pub fn log(self, details: &dyn std::fmt::Display) {
+        match self {
+            Self::ServiceUnavailable | Self::Conversation | Self::Other => {
+                tracing::error!("authentication error {:?}: {}", self, details);
+            }
+            _ => {
+                tracing::warn!("authentication error {:?}: {}", self, details);
+            }
+        }
+    }
+}
+
+//TODO: modules may translate their prompts and messages, only English ones are recognised here,
+// in `PasswordField::from_prompt` and in `Biometric::from_message`
+
+/// Returns true for messages sent by PAM when the password has to be changed
+fn is_password_expired_message(message: &str) -> bool {
+    let lower = message.to_lowercase();
+    lower.contains("password")
+        && (lower.contains("expired") || lower.contains("change your password"))
+}
+
+/// Parses the failed logins and remaining lockout time from pam_faillock messages like
+/// "The account is locked due to 3 failed logins." and "(10 minutes left to unlock)"
+fn parse_faillock_message(message: &str) -> (Option<u32>, Option<Duration>) {
+    let lower = message.to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == '.' || c == ',')
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let mut failed_opt = None;
+    let mut left_opt = None;
+    for pair in words.windows(2) {
+        let Ok(number) = pair[0].parse::<u64>() else {
+            continue;
+        };
+        let unit = pair[1];
+        if unit == "failed" && lower.contains("locked") {
+            failed_opt = u32::try_from(number).ok();
+        } else if lower.contains("left to unlock") || lower.contains("try again in") {
+            let secs = if unit.starts_with("hour") {
+                number * 60 * 60
+            } else if unit.starts_with("minute") {
+                number * 60
+            } else if unit.starts_with("second") {
+                number
+            } else {
+                continue;
+            };
+            left_opt = Some(left_opt.unwrap_or_default() + Duration::from_secs(secs));
+        }
+    }
+    (failed_opt, left_opt)
+}
+
+/// Converts errors sent by PAM during the conversation to localized messages where known
+pub fn pam_message_to_error(message: &str) -> String {
+    let lower = message.to_lowercase();
+    if lower.contains("do not match") || lower.contains("don't match") {
+        fl!("password-mismatch")
+    } else if let Some(reason) = message.strip_prefix("BAD PASSWORD:") {
+        // pam_pwquality explains why a new password was rejected
+        fl!("password-quality", reason = reason.trim())
+    } else if lower.contains("password unchanged") || lower.contains("have exhausted") {
+        fl!("password-change-failed")
+    } else if is_password_expired_message(message) {
+        fl!("password-expired")
+    } else {
+        message.to_string()
+    }
+}
+
 pub struct Common<M> {
     pub wayland_connection: Option<Connection>,
     pub keyboard_layout: Option<ZcosmicKeyboardLayoutV1>,
@@ -41,23 +362,47 @@ pub struct Common<M> {
     pub core: Core,
     pub error_opt: Option<String>,
     pub fallback_background: widget::image::Handle,
+    /// Wallpaper images by path, with blur applied
+    pub path_images: HashMap<PathBuf, widget::image::Handle>,
+    pub path_image_requests: HashSet<PathBuf>,
     pub layouts_opt: Option<Arc<xkb_data::KeyboardLayouts>>,
     pub network_icon_opt: Option<widget::Icon>,
     pub on_output_event: Option<Box<dyn Fn(OutputEvent, WlOutput) -> M>>,
     pub on_session_lock_event: Option<Box<dyn Fn(SessionLockEvent) -> M>>,
+    /// Submits a value to PAM, used to answer prompts without the user
+    pub on_submit: Option<Box<dyn Fn(String) -> M>>,
     pub output_names: HashMap<WlOutput, String>,
     pub power_info_opt: Option<(widget::Icon, f64)>,
     pub prompt_opt: Option<(String, bool, Option<String>)>,
+    pub prompt_kind: PromptKind,
+    /// Info messages and answered prompts of the current conversation
+    pub prompt_history: Vec<(PromptKind, String)>,
+    /// Number of prompts in the current conversation, including the current one
+    pub prompt_step: usize,
+    /// Set when PAM asks for a new password because the current one expired
+    pub password_change: bool,
+    /// New password that was entered, to check the confirmation before sending it
+    pub new_password_opt: Option<String>,
+    /// New password that was sent, to answer the confirmation prompt of PAM
+    pub confirmed_password_opt: Option<String>,
+    /// Failed authentication attempts since the greeter or lock screen was shown
+    pub failed_attempts: u32,
+    /// Focus the surface the pointer enters, used when the prompt is only on one output
+    pub focus_follows_pointer: bool,
+    /// Time when authentication is allowed again, after too many failed attempts
+    pub lockout_until_opt: Option<Instant>,
     pub rectangle_tracker: Option<RectangleTracker<(SurfaceId, bool)>>,
     pub rectangles: HashMap<(SurfaceId, bool), iced::Rectangle>,
     pub include_menu: bool,
     pub subsurface_rects: HashMap<WlOutput, Rectangle>,
     pub surface_ids: HashMap<WlOutput, SurfaceId>,
     pub subsurface_outputs: HashMap<SurfaceId, WlOutput>,
-    pub surface_images: HashMap<SurfaceId, widget::image::Handle>,
+    pub surface_backgrounds: HashMap<SurfaceId, Background>,
     pub surface_names: HashMap<SurfaceId, String>,
     pub text_input_ids: HashMap<String, widget::Id>,
     pub time: crate::time::Time,
+    pub wallpaper_blur_radius: f32,
+    pub wallpaper_dim: f32,
     pub window_size: HashMap<SurfaceId, Size>,
 }
 
@@ -70,7 +415,18 @@ pub enum Message {
     SubsurfaceOpened(SurfaceId),
     OutputEvent(OutputEvent, WlOutput),
     PowerInfo(Option<(f64, bool, bool)>),
+    PathImage(PathBuf, Option<widget::image::Handle>),
     Prompt(String, bool, Option<String>),
+    /// New message of the conversation, either a prompt or info
+    AuthPrompt(PromptKind, String),
+    /// Start of a new conversation
+    PromptReset,
+    /// The password expired and has to be changed in the current conversation
+    PasswordChange,
+    /// Authentication failed because of wrong credentials
+    AuthFailed,
+    /// Updates the lockout countdown
+    LockoutTick,
     SessionLockEvent(SessionLockEvent),
     Tick,
     Tz(jiff::tz::TimeZone),
@@ -109,18 +465,32 @@ impl<M: From<Message> + Send + 'static> Common<M> {
                 include_bytes!("../res/background.jpg").as_slice(),
             ),
             layouts_opt,
+            path_images: HashMap::new(),
+            path_image_requests: HashSet::new(),
             network_icon_opt: None,
             on_output_event: None,
             on_session_lock_event: None,
+            on_submit: None,
             output_names: HashMap::new(),
             power_info_opt: None,
             prompt_opt: None,
+            prompt_kind: PromptKind::Secret,
+            prompt_history: Vec::new(),
+            prompt_step: 0,
+            password_change: false,
+            new_password_opt: None,
+            confirmed_password_opt: None,
+            failed_attempts: 0,
+            focus_follows_pointer: false,
+            lockout_until_opt: None,
             subsurface_rects: HashMap::new(),
             surface_ids: HashMap::new(),
-            surface_images: HashMap::new(),
+            surface_backgrounds: HashMap::new(),
             surface_names: HashMap::new(),
             text_input_ids: HashMap::new(),
             time: crate::time::Time::new(),
+            wallpaper_blur_radius: 0.0,
+            wallpaper_dim: 0.0,
             window_size: HashMap::new(),
             battery_percent: 0.0,
             on_battery: false,
@@ -139,9 +509,12 @@ impl<M: From<Message> + Send + 'static> Common<M> {
         )
     }
 
-    pub fn update_wallpapers(&mut self, user_data: &UserData) {
+    /// Sets wallpapers from the wallpaper data of the user, returns a task if images have to be
+    /// blurred first. Call again after [`Message::PathImage`] to use the blurred images.
+    pub fn update_wallpapers(&mut self, user_data: &UserData) -> Task<M> {
+        let mut tasks = Vec::new();
         for (_output, surface_id) in self.surface_ids.iter() {
-            if self.surface_images.contains_key(surface_id) {
+            if self.surface_backgrounds.contains_key(surface_id) {
                 continue;
             }
 
@@ -151,43 +524,212 @@ impl<M: From<Message> + Send + 'static> Common<M> {
 
             tracing::info!("updating wallpaper for {:?}", output_name);
 
-            for (wallpaper_output_name, wallpaper_source) in user_data.bg_state.wallpapers.iter() {
-                if wallpaper_output_name == output_name {
-                    match wallpaper_source {
-                        BgSource::Path(path) => {
-                            match user_data.bg_path_data.get(path) {
-                                Some(bytes) => {
-                                    let image = widget::image::Handle::from_bytes(bytes.clone());
-                                    self.surface_images.insert(*surface_id, image);
-                                    //TODO: what to do about duplicates?
-                                }
-                                None => {
-                                    tracing::warn!(
-                                        "output {}: failed to find wallpaper data for source {:?}",
-                                        output_name,
-                                        path
-                                    );
-                                }
-                            }
-                            break;
-                        }
-                        BgSource::Color(color) => {
-                            //TODO: support color sources
-                            tracing::warn!(
-                                "output {}: unsupported source {:?}",
-                                output_name,
-                                color
-                            );
-                        }
+            match user_data.bg_source(output_name) {
+                Some(BgSource::Path(path)) => {
+                    let scaling_mode = user_data.bg_scaling_mode(output_name);
+                    if let Some(image) = self.path_images.get(path) {
+                        self.surface_backgrounds
+                            .insert(*surface_id, Background::Image(image.clone(), scaling_mode));
+                        continue;
+                    }
+
+                    let Some(bytes) = user_data.bg_path_data.get(path) else {
+                        tracing::warn!(
+                            "output {}: failed to find wallpaper data for source {:?}",
+                            output_name,
+                            path
+                        );
+                        continue;
+                    };
+
+                    if self.wallpaper_blur_radius <= 0.0 {
+                        let image = widget::image::Handle::from_bytes(bytes.clone());
+                        self.path_images.insert(path.clone(), image.clone());
+                        self.surface_backgrounds
+                            .insert(*surface_id, Background::Image(image, scaling_mode));
+                    } else if self.path_image_requests.insert(path.clone()) {
+                        let path = path.clone();
+                        let bytes = bytes.clone();
+                        let blur_radius = self.wallpaper_blur_radius;
+                        tasks.push(cosmic::task::future(async move {
+                            let image_opt = wallpaper_image(bytes, blur_radius).await;
+                            cosmic::Action::App(M::from(Message::PathImage(path, image_opt)))
+                        }));
                     }
                 }
+                Some(BgSource::Color(color)) => {
+                    self.surface_backgrounds
+                        .insert(*surface_id, Background::Color(color.clone()));
+                }
+                None => {}
             }
         }
+        Task::batch(tasks)
     }
 
-    pub fn update_user_data(&mut self, user_data: &UserData) {
-        self.update_wallpapers(user_data);
+    /// Clears the prompt and its history, when a new conversation starts
+    pub fn reset_prompts(&mut self) {
+        self.prompt_opt = None;
+        self.prompt_kind = PromptKind::Secret;
+        self.prompt_history.clear();
+        self.prompt_step = 0;
+        self.password_change = false;
+        self.new_password_opt = None;
+        self.confirmed_password_opt = None;
+    }
+
+    /// Time until authentication is allowed again, if locked out
+    pub fn lockout_remaining(&self) -> Option<Duration> {
+        self.lockout_until_opt?
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Shows the lockout countdown, or the number of failed attempts
+    pub fn attempts<'a, T: 'a>(&self) -> Option<Element<'a, T>> {
+        let text = if let Some(remaining) = self.lockout_remaining() {
+            // Round up, so the countdown does not show zero while still locked out
+            let secs = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+            fl!(
+                "lockout-countdown",
+                minutes = secs / 60,
+                seconds = format!("{:02}", secs % 60)
+            )
+        } else if self.failed_attempts > 0 {
+            fl!("failed-attempts", attempts = self.failed_attempts)
+        } else {
+            return None;
+        };
+
+        Some(
+            widget::container(widget::text::caption(text))
+                .width(Length::Fill)
+                .align_x(iced::Alignment::Center)
+                .into(),
+        )
+    }
+
+    /// Field of the password change the current prompt asks for
+    pub fn password_field(&self) -> Option<PasswordField> {
+        if !self.password_change {
+            return None;
+        }
+        // The new password is confirmed before PAM asks for the confirmation
+        if self.new_password_opt.is_some() {
+            return Some(PasswordField::Confirm);
+        }
+        let (prompt, _, _) = self.prompt_opt.as_ref()?;
+        PasswordField::from_prompt(prompt)
+    }
+
+    /// Placeholder of the prompt input, naming the field when changing the password
+    pub fn prompt_placeholder(&self, prompt: &str) -> String {
+        match self.password_field() {
+            Some(PasswordField::Current) => fl!("password-current"),
+            Some(PasswordField::New) => fl!("password-new"),
+            Some(PasswordField::Confirm) => fl!("password-confirm"),
+            None => prompt.to_string(),
+        }
+    }
+
+    /// Checks a value before it is sent to PAM. The new password is kept until it is confirmed,
+    /// so a mismatching confirmation restarts from the new password. PAM gets the confirmation
+    /// it asks for afterwards without the user.
+    pub fn check_submit(&mut self, value: &str) -> bool {
+        if let Some(new_password) = self.new_password_opt.take() {
+            if new_password != value {
+                self.error_opt = Some(fl!("password-mismatch"));
+                self.clear_prompt_value();
+                return false;
+            }
+            self.confirmed_password_opt = Some(new_password);
+            return true;
+        }
+        match self.password_field() {
+            Some(PasswordField::New) => {
+                self.new_password_opt = Some(value.to_string());
+                self.confirmed_password_opt = None;
+                self.clear_prompt_value();
+                false
+            }
+            Some(PasswordField::Current | PasswordField::Confirm) | None => true,
+        }
+    }
 
+    fn clear_prompt_value(&mut self) {
+        if let Some((_, _, value_opt)) = &mut self.prompt_opt {
+            *value_opt = Some(String::new());
+        }
+    }
+
+    /// Shows info messages and answered prompts above the current prompt, with the current step
+    /// when there is more than one
+    pub fn prompt_history<'a, T: 'a>(&'a self) -> Option<Element<'a, T>> {
+        if self.prompt_history.is_empty() && self.prompt_step < 2 && !self.password_change {
+            return None;
+        }
+
+        let mut column = widget::column::with_capacity(self.prompt_history.len() + 2)
+            .spacing(4.0)
+            .align_x(iced::Alignment::Center);
+        if self.password_change {
+            column = column.push(widget::text::heading(fl!("password-expired")));
+        }
+        for (kind, message) in self.prompt_history.iter() {
+            column = column.push(match kind {
+                PromptKind::Info | PromptKind::Error => widget::text(message).into(),
+                PromptKind::Secret | PromptKind::Visible => widget::row::with_capacity(2)
+                    .spacing(8.0)
+                    .align_y(iced::Alignment::Center)
+                    .push(widget::icon::from_name("object-select-symbolic").size(16))
+                    .push(widget::text::caption(message))
+                    .into(),
+            });
+        }
+        if self.prompt_step > 1 {
+            column = column.push(widget::text::caption(fl!(
+                "prompt-step",
+                step = self.prompt_step
+            )));
+        }
+
+        Some(
+            widget::container(column)
+                .width(Length::Fill)
+                .align_x(iced::Alignment::Center)
+                .into(),
+        )
+    }
+
+    /// Draws the background of a surface, using the fallback background if none is set
+    pub fn background<'a, T: 'a>(&'a self, surface_id: SurfaceId) -> Element<'a, T> {
+        self.dimmed(match self.surface_backgrounds.get(&surface_id) {
+            Some(background) => background.view(1.0),
+            None => widget::image(&self.fallback_background)
+                .content_fit(iced::ContentFit::Cover)
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .into(),
+        })
+    }
+
+    /// Draws a black layer over a background, if wallpapers should be dimmed
+    pub fn dimmed<'a, T: 'a>(&self, background: Element<'a, T>) -> Element<'a, T> {
+        if self.wallpaper_dim <= 0.0 {
+            return background;
+        }
+        let dim = iced::Color {
+            a: self.wallpaper_dim.min(1.0),
+            ..iced::Color::BLACK
+        };
+        iced::widget::stack![
+            background,
+            fill_background(widget::space::horizontal(), iced::Background::Color(dim)),
+        ]
+        .into()
+    }
+
+    pub fn update_user_data(&mut self, user_data: &UserData) {
         // From cosmic-applet-input-sources
         if let Some(keyboard_layouts) = &self.layouts_opt
             && let Some(xkb_config) = &user_data.xkb_config_opt
@@ -236,6 +778,11 @@ impl<M: From<Message> + Send + 'static> Common<M> {
 
     pub fn update(&mut self, message: Message) -> Task<M> {
         match message {
+            Message::PathImage(path, image_opt) => {
+                if let Some(image) = image_opt {
+                    self.path_images.insert(path, image);
+                }
+            }
             Message::CapsLock(caps_lock) => {
                 self.caps_lock = caps_lock;
             }
@@ -292,6 +839,75 @@ impl<M: From<Message> + Send + 'static> Common<M> {
                     self.update_battery(level, on_battery);
                 }
             }
+            Message::AuthPrompt(kind, prompt) => {
+                let (failed_opt, left_opt) = match kind {
+                    PromptKind::Info | PromptKind::Error => parse_faillock_message(&prompt),
+                    PromptKind::Secret | PromptKind::Visible => (None, None),
+                };
+                if let Some(failed) = failed_opt {
+                    self.failed_attempts = self.failed_attempts.max(failed);
+                }
+                if let Some(left) = left_opt {
+                    self.lockout_until_opt = Some(Instant::now() + left);
+                }
+                if failed_opt.is_some() || left_opt.is_some() {
+                    // Shown as the lockout countdown instead
+                    return Task::none();
+                }
+                if matches!(kind, PromptKind::Info | PromptKind::Error)
+                    && is_password_expired_message(&prompt)
+                {
+                    // Shown as the heading of the password change instead
+                    self.password_change = true;
+                    return Task::none();
+                }
+                match kind {
+                    PromptKind::Info => {
+                        self.prompt_history.push((kind, prompt));
+                        return Task::none();
+                    }
+                    PromptKind::Error => {
+                        self.error_opt = Some(pam_message_to_error(&prompt));
+                        return Task::none();
+                    }
+                    PromptKind::Secret | PromptKind::Visible => {}
+                }
+                // Keep the previous prompt visible as an answered step
+                if let Some((prev_prompt, _, Some(_))) = self.prompt_opt.take() {
+                    self.prompt_history.push((self.prompt_kind, prev_prompt));
+                }
+                self.prompt_kind = kind;
+                self.prompt_step += 1;
+                let task = self.update(Message::Prompt(
+                    prompt,
+                    kind == PromptKind::Secret,
+                    Some(String::new()),
+                ));
+                if self.password_field() == Some(PasswordField::Confirm)
+                    && let Some(confirmed_password) = self.confirmed_password_opt.take()
+                    && let Some(on_submit) = &self.on_submit
+                {
+                    return Task::batch([
+                        task,
+                        Task::done(cosmic::Action::App(on_submit(confirmed_password))),
+                    ]);
+                }
+                return task;
+            }
+            Message::PromptReset => {
+                self.reset_prompts();
+            }
+            Message::PasswordChange => {
+                self.password_change = true;
+            }
+            Message::AuthFailed => {
+                self.failed_attempts += 1;
+            }
+            Message::LockoutTick => {
+                if self.lockout_remaining().is_none() {
+                    self.lockout_until_opt = None;
+                }
+            }
             Message::Prompt(prompt, secret, value_opt) => {
                 let prompt_was_none = self.prompt_opt.is_none();
                 self.prompt_opt = Some((prompt, secret, value_opt));
@@ -430,12 +1046,25 @@ impl<M: From<Message> + Send + 'static> Common<M> {
             _ => None,
         }));
 
+        if self.focus_follows_pointer {
+            // Follow the pointer, so the prompt can be shown where the user is looking
+            subscriptions.push(event::listen_with(|event, _status, id| match event {
+                iced::Event::Mouse(iced::mouse::Event::CursorEntered) => Some(Message::Focus(id)),
+                _ => None,
+            }));
+        }
+
         if let Some(conn) = self.wayland_connection.clone() {
             subscriptions.push(
                 keyboard_layout_wayland::subscription(conn).map(Message::KeyboardLayoutWayland),
             );
         }
 
+        if self.lockout_until_opt.is_some() {
+            subscriptions
+                .push(iced::time::every(Duration::from_secs(1)).map(|_| Message::LockoutTick));
+        }
+
         #[cfg(feature = "networkmanager")]
         {
             subscriptions.push(crate::networkmanager::subscription().map(Message::NetworkIcon));
@@ -490,3 +1119,84 @@ impl<M> Common<M> {
         ));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use greetd_ipc::ErrorType;
+    use pam_client::ErrorCode;
+
+    #[test]
+    fn auth_error_from_pam() {
+        for (code, error) in [
+            (ErrorCode::AUTH_ERR, AuthError::Credentials),
+            (ErrorCode::CRED_INSUFFICIENT, AuthError::Credentials),
+            (ErrorCode::PERM_DENIED, AuthError::Denied),
+            (ErrorCode::MAXTRIES, AuthError::MaxTries),
+            (ErrorCode::NEW_AUTHTOK_REQD, AuthError::Expired),
+            (ErrorCode::AUTHTOK_ERR, AuthError::PasswordChange),
+            (ErrorCode::ACCT_EXPIRED, AuthError::Account),
+            (ErrorCode::USER_UNKNOWN, AuthError::Account),
+            (ErrorCode::AUTHINFO_UNAVAIL, AuthError::ServiceUnavailable),
+            (ErrorCode::CONV_ERR, AuthError::Conversation),
+            (ErrorCode::BUF_ERR, AuthError::Other),
+        ] {
+            assert_eq!(AuthError::from_pam(code), error, "{code:?}");
+        }
+    }
+
+    #[test]
+    fn auth_error_from_greetd() {
+        for (description, error) in [
+            ("pam_authenticate: AUTH_ERR", AuthError::Credentials),
+            ("authentication failed", AuthError::Credentials),
+            ("pam_acct_mgmt: PERM_DENIED", AuthError::Denied),
+            ("pam_authenticate: MAXTRIES", AuthError::MaxTries),
+            ("pam_acct_mgmt: NEW_AUTHTOK_REQD", AuthError::Expired),
+            ("pam_chauthtok: AUTHTOK_ERR", AuthError::PasswordChange),
+            ("pam_acct_mgmt: ACCT_EXPIRED", AuthError::Account),
+            ("pam_authenticate: USER_UNKNOWN", AuthError::Account),
+            (
+                "pam_authenticate: AUTHINFO_UNAVAIL",
+                AuthError::ServiceUnavailable,
+            ),
+            ("pam_authenticate: CONV_ERR", AuthError::Conversation),
+        ] {
+            assert_eq!(
+                AuthError::from_greetd(&ErrorType::AuthError, description),
+                error,
+                "{description}"
+            );
+        }
+
+        // Errors that are not from PAM are never credential errors
+        assert_eq!(
+            AuthError::from_greetd(&ErrorType::Error, "pam_authenticate: AUTH_ERR"),
+            AuthError::Other
+        );
+        assert_eq!(
+            AuthError::from_greetd(&ErrorType::Error, "session already started"),
+            AuthError::Other
+        );
+    }
+
+    #[test]
+    fn auth_error_messages() {
+        crate::localize::localizer();
+
+        for error in [
+            AuthError::Credentials,
+            AuthError::Denied,
+            AuthError::MaxTries,
+            AuthError::Expired,
+            AuthError::PasswordChange,
+            AuthError::Account,
+            AuthError::ServiceUnavailable,
+            AuthError::Conversation,
+            AuthError::Other,
+        ] {
+            assert!(!error.message().is_empty(), "{error:?}");
+        }
+        assert_ne!(AuthError::Credentials.message(), AuthError::Other.message());
+    }
+}
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 mod ipc;
+mod session;
 
 use crate::wayland::{self, WaylandUpdate};
 use cctk::sctk::reexports::calloop;
@@ -10,10 +11,9 @@ use cosmic::app::{Core, Settings, Task};
 use cosmic::cctk::wayland_protocols::xdg::shell::client::xdg_positioner::Gravity;
 use cosmic::cosmic_config::{self, ConfigSet};
 use cosmic::cosmic_theme::{self, CosmicPalette};
-use cosmic::desktop::fde::{DesktopEntry, get_languages_from_env};
 use cosmic::iced::event::listen_with;
 use cosmic::iced::event::wayland::OutputEvent;
-use cosmic::iced::futures::SinkExt;
+use cosmic::iced::futures::{SinkExt, StreamExt};
 use cosmic::iced::platform_specific::runtime::wayland::layer_surface::{
     IcedMargin, IcedOutput, SctkLayerSurfaceSettings,
 };
@@ -30,14 +30,21 @@ use cosmic::iced::{
 use cosmic::widget::{id_container, text};
 use cosmic::{Element, executor, surface, theme, widget};
 use cosmic_greeter_config::Config as CosmicGreeterConfig;
-use cosmic_greeter_daemon::{UserData, UserFilter};
+use cosmic_greeter_config::session::Autologin;
+use cosmic_greeter_config::state::{SessionStart, State as GreeterState};
+use cosmic_greeter_config::user::{SessionType, UserState};
+use cosmic_greeter_daemon::{BgSource, UserData, UserFilter};
 use cosmic_randr_shell::{KdlParseWithError, List};
 use cosmic_settings_a11y_manager_subscription::{AccessibilityEvent, AccessibilityRequest};
 use greetd_ipc::Request;
 use kdl::KdlDocument;
-use std::collections::{HashMap, hash_map};
+use std::any::TypeId;
+use std::collections::{HashMap, HashSet, hash_map};
 use std::error::Error;
+use std::io::{Read, Seek};
 use std::num::NonZeroU32;
+use std::os::fd::OwnedFd;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::{Arc, LazyLock};
 use std::time::{Duration, Instant};
@@ -54,6 +61,7 @@ use zbus::{Connection, proxy};
 
 use crate::common::{self, Common, DEFAULT_MENU_ITEM_HEIGHT};
 use crate::fl;
+use session::Session;
 
 static USERNAME_ID: LazyLock<iced::id::Id> = LazyLock::new(|| iced::id::Id::new("username-id"));
 
@@ -63,18 +71,86 @@ static USERNAME_ID: LazyLock<iced::id::Id> = LazyLock::new(|| iced::id::Id::new(
     default_path = "/com/system76/CosmicGreeter"
 )]
 trait Greeter {
-    async fn get_user_data(&self) -> Result<String, zbus::Error>;
+    async fn list_users(&self) -> Result<Vec<(u32, String, String)>, zbus::Error>;
+
+    async fn get_user_data(&self, uid: u32) -> Result<String, zbus::Error>;
+
+    async fn get_wallpaper(
+        &self,
+        uid: u32,
+        output: &str,
+    ) -> Result<zbus::zvariant::OwnedFd, zbus::Error>;
+
+    #[zbus(signal)]
+    fn user_data_changed(&self, uid: u32) -> zbus::Result<()>;
 }
 
-async fn user_data_dbus() -> Result<Vec<UserData>, Box<dyn Error>> {
+async fn user_list_dbus() -> Result<Vec<UserData>, Box<dyn Error>> {
     let connection = Connection::system().await?;
 
     // `dbus_proxy` macro creates `MyGreaterProxy` based on `Notifications` trait.
     let proxy = GreeterProxy::new(&connection).await?;
-    let reply = proxy.get_user_data().await?;
+    let reply = proxy.list_users().await?;
+
+    Ok(reply
+        .into_iter()
+        .map(|(uid, name, full_name)| UserData {
+            uid,
+            name,
+            full_name,
+            ..Default::default()
+        })
+        .collect())
+}
+
+async fn user_data_dbus(uid: u32) -> Result<UserData, Box<dyn Error + Send + Sync>> {
+    let connection = Connection::system().await?;
+    let proxy = GreeterProxy::new(&connection).await?;
+    let reply = proxy.get_user_data(uid).await?;
 
-    let user_datas: Vec<UserData> = ron::from_str(&reply)?;
-    Ok(user_datas)
+    let user_data: UserData = ron::from_str(&reply)?;
+    Ok(user_data)
+}
+
+async fn wallpaper_dbus(uid: u32, output: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let connection = Connection::system().await?;
+    let proxy = GreeterProxy::new(&connection).await?;
+    let fd: OwnedFd = proxy.get_wallpaper(uid, output).await?.into();
+
+    // The daemon provides a sealed memfd, which is read from the start
+    let mut file = fs::File::from(fd);
+    file.rewind()?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Reports users whose data has changed, according to the daemon
+fn user_data_changed_subscription() -> Subscription<Message> {
+    struct UserDataChangedSubscription;
+    Subscription::run_with(TypeId::of::<UserDataChangedSubscription>(), |_| {
+        cosmic::iced::stream::channel(
+            16,
+            |mut msg_tx: iced::futures::channel::mpsc::Sender<_>| async move {
+                let res: zbus::Result<()> = async {
+                    let connection = Connection::system().await?;
+                    let proxy = GreeterProxy::new(&connection).await?;
+                    let mut changes = proxy.receive_user_data_changed().await?;
+                    while let Some(change) = changes.next().await {
+                        let args = change.args()?;
+                        _ = msg_tx.send(Message::UserDataChanged(args.uid)).await;
+                    }
+                    Ok(())
+                }
+                .await;
+                if let Err(err) = res {
+                    tracing::warn!("failed to receive user data changes: {}", err);
+                }
+
+                futures_util::future::pending().await
+            },
+        )
+    })
 }
 
 fn user_data_fallback() -> Vec<UserData> {
@@ -126,7 +202,7 @@ pub fn main() -> Result<(), Box<dyn Error>> {
         .enable_all()
         .build()
         .unwrap();
-    let mut user_datas = match runtime.block_on(user_data_dbus()) {
+    let mut user_datas = match runtime.block_on(user_list_dbus()) {
         Ok(ok) => ok,
         Err(err) => {
             tracing::error!("failed to load user data from daemon: {}", err);
@@ -144,153 +220,48 @@ pub fn main() -> Result<(), Box<dyn Error>> {
             .is_ok()
     });
 
-    enum SessionType {
-        X11,
-        Wayland,
-    }
-
-    let session_dirs = xdg::BaseDirectories::with_prefix("wayland-sessions")
-        .get_data_dirs()
-        .into_iter()
-        .map(|dir| (dir, SessionType::Wayland))
-        .chain(
-            xdg::BaseDirectories::with_prefix("xsessions")
-                .get_data_dirs()
-                .into_iter()
-                .map(|dir| (dir, SessionType::X11)),
-        );
-
-    let sessions = {
-        let mut sessions = HashMap::new();
-        let locales = get_languages_from_env();
-        for (session_dir, session_type) in session_dirs {
-            let read_dir = match fs::read_dir(&session_dir) {
-                Ok(ok) => ok,
-                Err(err) => {
-                    tracing::warn!(
-                        "failed to read session directory {:?}: {:?}",
-                        session_dir,
-                        err
-                    );
-                    continue;
-                }
-            };
-
-            for dir_entry_res in read_dir {
-                let dir_entry = match dir_entry_res {
-                    Ok(ok) => ok,
-                    Err(err) => {
-                        tracing::warn!(
-                            "failed to read session directory {:?} entry: {:?}",
-                            session_dir,
-                            err
-                        );
-                        continue;
-                    }
-                };
-
-                let entry = match DesktopEntry::from_path(dir_entry.path(), Some(&locales)) {
-                    Ok(ok) => ok,
-                    Err(err) => {
-                        tracing::warn!(
-                            "failed to read session file {:?}: {:?}",
-                            dir_entry.path(),
-                            err
-                        );
-                        continue;
-                    }
-                };
-
-                let name = match entry.name(&locales) {
-                    Some(some) => some,
-                    None => {
-                        tracing::warn!(
-                            "failed to read session file {:?}: no Desktop Entry/Name attribute",
-                            dir_entry.path()
-                        );
-                        continue;
-                    }
-                };
-
-                let exec = match entry.exec() {
-                    Some(some) => some,
-                    None => {
-                        tracing::warn!(
-                            "failed to read session file {:?}: no Desktop Entry/Exec attribute",
-                            dir_entry.path()
-                        );
-                        continue;
-                    }
-                };
-
-                let mut command = Vec::new();
-                let mut env = Vec::new();
-                match session_type {
-                    SessionType::X11 => {
-                        //TODO: xinit may be better, but more complicated to set up
-                        command.push("startx".to_string());
-                        env.push("XDG_SESSION_TYPE=x11".to_string());
-                    }
-                    SessionType::Wayland => {
-                        env.push("XDG_SESSION_TYPE=wayland".to_string());
-                    }
-                };
-
-                if let Some(desktop_names) = entry
-                    .groups
-                    .desktop_entry()
-                    .and_then(|g| g.entry("DesktopNames"))
-                {
-                    env.push(format!("XDG_CURRENT_DESKTOP={desktop_names}"));
-                    if let Some(name) = desktop_names.split(':').next() {
-                        env.push(format!("XDG_SESSION_DESKTOP={name}"));
-                    }
-                }
-
-                // Session exec may contain environmental variables
-                command.push("/usr/bin/env".to_string());
-
-                // To ensure the env is set correctly, we also set it in the session command
-                for arg in env.iter() {
-                    command.push(arg.clone());
-                }
+    let sessions = session::load(&greeter_config);
 
-                match shlex::split(exec) {
-                    Some(args) => {
-                        for arg in args {
-                            command.push(arg)
-                        }
-                    }
-                    None => {
-                        tracing::warn!(
-                            "failed to parse session file {:?} Exec field {:?}",
-                            dir_entry.path(),
-                            exec
-                        );
-                        continue;
-                    }
-                };
+    // The session started last time is only reported once, if it did not last long
+    let (mut greeter_state, greeter_state_handler) = GreeterState::load();
+    let mut session_crash_opt = None;
+    if let Some(start) = greeter_state.last_session_start.take() {
+        if let Some(handler) = &greeter_state_handler
+            && let Err(err) = handler.set("last_session_start", &greeter_state.last_session_start)
+        {
+            tracing::error!("failed to clear last session start: {:?}", err);
+        }
+        if start
+            .elapsed()
+            .is_some_and(|elapsed| elapsed < SESSION_CRASH_TIMEOUT)
+        {
+            tracing::warn!("last session {:?} ended unexpectedly", start);
+            session_crash_opt = Some(start);
+        }
+    }
 
-                tracing::info!("session {} using command {:?} env {:?}", name, command, env);
-                if let Some(some) = sessions.insert(name.to_string(), (command, env)) {
-                    tracing::warn!("session {} overwrote old command {:?}", name, some);
-                }
-            }
+    // Autologin happens once per boot, the greeter is shown when the session ends
+    let autologin_opt = greeter_config.autologin.clone().filter(|autologin| {
+        if Path::new(AUTOLOGIN_MARKER).exists() {
+            tracing::info!("not logging in {} again since boot", autologin.user);
+            false
+        } else {
+            true
         }
-        sessions
-    };
+    });
 
     let logind_available = cfg!(feature = "logind") && crate::logind::is_available();
 
     let flags = Flags {
-        user_icons: user_datas
-            .iter_mut()
-            .map(|d| d.icon_opt.take().map(widget::image::Handle::from_bytes))
-            .collect(),
+        // Icons are loaded with the rest of the user data when a user is selected
+        user_icons: vec![None; user_datas.len()],
         user_datas,
         sessions,
         greeter_config,
         greeter_config_handler,
+        greeter_state_handler,
+        session_crash_opt,
+        autologin_opt,
         logind_available,
     };
 
@@ -301,16 +272,129 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Sessions ending sooner than this after being started are reported on the next start. This
+/// includes the time greetd takes to start the greeter again.
+const SESSION_CRASH_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Created when the autologin session is created, removed on reboot with `/run`
+const AUTOLOGIN_MARKER: &str = "/run/cosmic-greeter/autologin";
+
+fn create_autologin_marker() -> bool {
+    match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(AUTOLOGIN_MARKER)
+    {
+        Ok(_) => true,
+        Err(err) => {
+            tracing::error!(
+                "failed to create autologin marker {:?}: {:?}",
+                AUTOLOGIN_MARKER,
+                err
+            );
+            false
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Flags {
     user_datas: Vec<UserData>,
     user_icons: Vec<Option<widget::image::Handle>>,
-    sessions: HashMap<String, (Vec<String>, Vec<String>)>,
+    sessions: Vec<Session>,
     greeter_config: CosmicGreeterConfig,
     greeter_config_handler: Option<cosmic_config::Config>,
+    greeter_state_handler: Option<cosmic_config::Config>,
+    /// Session that ended shortly after the greeter started it last time
+    session_crash_opt: Option<SessionStart>,
+    autologin_opt: Option<Autologin>,
     logind_available: bool,
 }
 
+impl Flags {
+    fn user_state(&self, data_idx: Option<usize>) -> Option<&UserState> {
+        let user_data = self.user_datas.get(data_idx?)?;
+        self.greeter_config
+            .users
+            .get(&NonZeroU32::new(user_data.uid)?)
+    }
+
+    /// Finds a session by desktop file id, preferring the given display server when the id
+    /// is used by both a Wayland and an X11 session
+    fn session(&self, id: &str, session_type_opt: Option<SessionType>) -> Option<&Session> {
+        let mut found = None;
+        for session in self.sessions.iter().filter(|session| session.id == id) {
+            if session.session_type == session_type_opt.unwrap_or(SessionType::Wayland) {
+                return Some(session);
+            }
+            found = found.or(Some(session));
+        }
+        found
+    }
+
+    /// Finds the session a user last logged into, if it is still installed
+    fn last_session(&self, state: &UserState) -> Option<&Session> {
+        let last_session = state.last_session.as_deref()?;
+        // Older versions stored the session name instead of the desktop file id
+        self.session(last_session, state.last_session_type)
+            .or_else(|| {
+                self.sessions
+                    .iter()
+                    .find(|session| session.name == last_session)
+            })
+    }
+
+    /// Returns the remembered session of a user when it is no longer installed
+    fn missing_last_session(&self, data_idx: Option<usize>) -> Option<&str> {
+        let state = self.user_state(data_idx)?;
+        match self.last_session(state) {
+            Some(_) => None,
+            None => state.last_session.as_deref(),
+        }
+    }
+
+    /// Picks the session of a user: the last session they logged into, then the session set in
+    /// AccountsService, then the configured default, then the first session by name
+    fn user_session(&self, data_idx: Option<usize>) -> Option<&Session> {
+        let state_opt = self.user_state(data_idx);
+        let session_type_opt = state_opt.and_then(|state| state.last_session_type);
+        if let Some(session) = state_opt.and_then(|state| self.last_session(state)) {
+            return Some(session);
+        }
+
+        if let Some(account) = data_idx
+            .and_then(|i| self.user_datas.get(i))
+            .and_then(|user_data| user_data.account_opt.as_ref())
+        {
+            let mut ids = [
+                (account.session.as_deref(), session_type_opt),
+                (account.x_session.as_deref(), Some(SessionType::X11)),
+            ];
+            if session_type_opt == Some(SessionType::X11) {
+                ids.reverse();
+            }
+            for (id, session_type_opt) in ids {
+                if let Some(session) = id.and_then(|id| self.session(id, session_type_opt)) {
+                    return Some(session);
+                }
+            }
+        }
+
+        if let Some(default_session) = &self.greeter_config.default_session {
+            if let Some(session) = self.session(default_session, session_type_opt).or_else(|| {
+                self.sessions
+                    .iter()
+                    .find(|session| &session.name == default_session)
+            }) {
+                return Some(session);
+            }
+            tracing::warn!("default session {:?} not found", default_session);
+        }
+
+        self.sessions.first()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum SocketState {
     /// Opening GREETD_SOCK
@@ -319,8 +403,8 @@ pub enum SocketState {
     Open,
     /// No GREETD_SOCK variable set
     NotSet,
-    /// Failed to open GREETD_SOCK
-    Error(Arc<io::Error>),
+    /// Failed to open GREETD_SOCK, trying again
+    Retrying(Arc<io::Error>),
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -367,6 +451,11 @@ pub enum Message {
     DialogCancel,
     DialogConfirm,
     DropdownToggle(Dropdown),
+    SessionCrashRetry,
+    SessionCrashChoose,
+    AutologinTick,
+    AutologinCancel,
+    AutologinNow,
     Error(String),
     Exit,
     // Sets channel used to communicate with the greetd IPC subscription.
@@ -383,17 +472,20 @@ pub enum Message {
     Reload(cosmic::Theme),
     RepositionMenu(window::Id, Size),
     Restart,
-    Session(String),
+    Session(Session),
     Shutdown,
     Socket(SocketState),
     Surface(surface::Action),
     Suspend,
+    UserData(u32, Option<Box<UserData>>),
+    UserDataChanged(u32),
     Username(String),
     EnterUser(bool, String),
     ScreenReader(bool),
     Magnifier(bool),
     HighContrast(bool),
     InvertColors(bool),
+    Wallpaper(u32, String, Option<widget::image::Handle>),
     WaylandUpdate(WaylandUpdate),
 }
 
@@ -411,12 +503,24 @@ pub struct App {
     socket_state: SocketState,
     usernames: Vec<(String, String)>,
     selected_username: NameIndexPair,
-    session_names: Vec<String>,
-    selected_session: String,
+    /// Users with data loaded from the daemon
+    user_data_loaded: HashSet<u32>,
+    /// Wallpapers loaded from the daemon, by uid and output name
+    wallpapers: HashMap<(u32, String), widget::image::Handle>,
+    wallpaper_requests: HashSet<(u32, String)>,
+    selected_session: Option<Session>,
+    /// The session was chosen by the user, and is kept when user data loads
+    session_chosen: bool,
+    /// Why the selected session is not the one the user logged into last time
+    session_notice_opt: Option<String>,
+    /// Logging in the autologin user when the countdown ends
+    autologin_deadline_opt: Option<Instant>,
     dialog_page_opt: Option<DialogPage>,
     dropdown_opt: Option<Dropdown>,
     heartbeat_handle: Option<cosmic::iced::task::Handle>,
     entering_name: bool,
+    /// Autologin was cancelled, no session is created until a user is picked
+    choosing_user: bool,
     theme_builder: cosmic_theme::ThemeBuilder,
     surface_id_pairs: Vec<(window::Id, window::Id)>,
 
@@ -472,7 +576,82 @@ impl App {
         .discard()
     }
 
+    /// Finds the output showing the login prompt, which follows focus and the pointer
+    fn prompt_output_name(&self) -> Option<&str> {
+        if let Some(output_name) = self
+            .common
+            .active_surface_id_opt
+            .and_then(|id| self.common.surface_names.get(&id))
+        {
+            return Some(output_name);
+        }
+
+        // Before any output is focused, prefer the primary output
+        if let Some(output) = self.randr_list.as_ref().and_then(|list| {
+            list.outputs
+                .values()
+                .find(|output| output.xwayland_primary == Some(true))
+        }) && self
+            .common
+            .output_names
+            .values()
+            .any(|output_name| output_name == &output.name)
+        {
+            return Some(&output.name);
+        }
+
+        self.common.output_names.values().min().map(String::as_str)
+    }
+
+    /// Whether the login prompt is shown on the output of a surface
+    fn shows_prompt(&self, id: SurfaceId) -> bool {
+        if !self.flags.greeter_config.single_output_prompt {
+            return true;
+        }
+        let Some(output_name) = self.common.surface_names.get(&id) else {
+            return true;
+        };
+        self.prompt_output_name()
+            .is_none_or(|prompt_output_name| prompt_output_name == output_name)
+    }
+
+    /// Shows only the clock, on outputs without the login prompt
+    fn clock(&self, id: SurfaceId) -> Element<'_, Message> {
+        let military_time = self
+            .selected_username
+            .data_idx
+            .and_then(|i| self.flags.user_datas.get(i))
+            .map(|user_data| user_data.time_applet_config.military_time)
+            .unwrap_or_default();
+        let clock = widget::layer_container(self.common.time.date_time_widget(military_time))
+            .layer(cosmic::cosmic_theme::Layer::Background)
+            .padding(16)
+            .class(cosmic::theme::Container::Custom(Box::new(menu_style)));
+        let clock = if let Some(t) = self.common.rectangle_tracker.as_ref() {
+            Element::from(t.container((id, false), clock))
+        } else {
+            clock.into()
+        };
+        widget::container(widget::column::with_children(vec![
+            widget::space::vertical()
+                .height(Length::FillPortion(1))
+                .into(),
+            clock,
+            widget::space::vertical()
+                .height(Length::FillPortion(4))
+                .into(),
+        ]))
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .align_x(Alignment::Center)
+        .into()
+    }
+
     fn menu(&self, id: SurfaceId) -> Element<'_, Message> {
+        if !self.shows_prompt(id) {
+            return self.clock(id);
+        }
+
         let window_width = self
             .common
             .window_size
@@ -644,13 +823,44 @@ impl App {
             )
             .position(widget::popover::Position::Bottom);
             if matches!(self.dropdown_opt, Some(Dropdown::Session)) {
-                let mut items = Vec::with_capacity(self.session_names.len());
-                for session_name in self.session_names.iter() {
-                    items.push(menu_checklist(
-                        session_name,
-                        session_name == &self.selected_session,
-                        Message::Session(session_name.clone()),
-                    ));
+                let mut items = Vec::with_capacity(self.flags.sessions.len());
+                for session in self.flags.sessions.iter() {
+                    let icon = match &session.icon_opt {
+                        Some(icon) if Path::new(icon).is_absolute() => {
+                            widget::icon::from_path(PathBuf::from(icon))
+                        }
+                        Some(icon) => widget::icon::from_name(icon.as_str()).handle(),
+                        None => widget::icon::from_name("desktop-symbolic").handle(),
+                    };
+                    let item = widget::menu::menu_button(vec![
+                        if self.selected_session.as_ref() == Some(session) {
+                            widget::icon::from_name("object-select-symbolic")
+                                .size(16)
+                                .icon()
+                                .width(Length::Fixed(16.0))
+                                .into()
+                        } else {
+                            widget::space::horizontal()
+                                .width(Length::Fixed(17.0))
+                                .into()
+                        },
+                        widget::space::horizontal().width(Length::Fixed(8.0)).into(),
+                        widget::icon(icon).size(16).into(),
+                        widget::space::horizontal().width(Length::Fixed(8.0)).into(),
+                        widget::text(session.label())
+                            .align_x(iced::alignment::Horizontal::Left)
+                            .into(),
+                    ])
+                    .on_press(Message::Session(session.clone()));
+                    items.push(match &session.comment_opt {
+                        Some(comment) => widget::tooltip(
+                            item,
+                            widget::text(comment),
+                            widget::tooltip::Position::Right,
+                        )
+                        .into(),
+                        None => item.into(),
+                    });
                 }
                 session_button = session_button.popup(dropdown_menu(items));
             }
@@ -781,7 +991,9 @@ impl App {
                         .iter()
                         .zip(self.flags.user_icons.iter())
                     {
-                        if !self.entering_name && user_data.name == self.selected_username.username
+                        if !self.entering_name
+                            && !self.choosing_user
+                            && user_data.name == self.selected_username.username
                         {
                             // Display user icon or empty transparent box
                             if let Some(icon_handle) = user_icon {
@@ -827,84 +1039,145 @@ impl App {
                             .on_submit(Message::Username),
                         )
                     }
-                    if let Some((prompt, secret, value_opt)) = &self.common.prompt_opt {
-                        match value_opt {
-                            Some(value) => {
-                                // Only show password input when not authenticating
-                                if !self.authenticating {
-                                    let text_input_id = self
-                                        .common
-                                        .surface_names
-                                        .get(&id)
-                                        .and_then(|id| self.common.text_input_ids.get(id))
-                                        .cloned()
-                                        .unwrap_or_else(|| cosmic::widget::Id::new("text_input"));
-                                    let mut text_input = widget::secure_input(
-                                        prompt.clone(),
-                                        value.as_str(),
-                                        Some(
-                                            common::Message::Prompt(
-                                                prompt.clone(),
-                                                !*secret,
-                                                Some(value.clone()),
-                                            )
-                                            .into(),
-                                        ),
-                                        *secret,
-                                    )
-                                    .id(text_input_id)
-                                    .on_input(|input| {
+                    if let Some(history) = self.common.prompt_history() {
+                        column = column.push(history);
+                    }
+                    // Only show password input when not authenticating
+                    if let Some((prompt, secret, Some(value))) = &self.common.prompt_opt
+                        && !self.authenticating
+                    {
+                        let text_input_id = self
+                            .common
+                            .surface_names
+                            .get(&id)
+                            .and_then(|id| self.common.text_input_ids.get(id))
+                            .cloned()
+                            .unwrap_or_else(|| cosmic::widget::Id::new("text_input"));
+                        let mut text_input =
+                            if self.common.prompt_kind == common::PromptKind::Visible {
+                                widget::text_input(
+                                    self.common.prompt_placeholder(prompt),
+                                    value.as_str(),
+                                )
+                            } else {
+                                widget::secure_input(
+                                    self.common.prompt_placeholder(prompt),
+                                    value.as_str(),
+                                    Some(
                                         common::Message::Prompt(
                                             prompt.clone(),
-                                            *secret,
-                                            Some(input),
+                                            !*secret,
+                                            Some(value.clone()),
                                         )
+                                        .into(),
+                                    ),
+                                    *secret,
+                                )
+                            }
+                            .id(text_input_id);
+
+                        // Don't allow input when locked out
+                        if self.common.lockout_remaining().is_none() {
+                            text_input = text_input
+                                .on_input(|input| {
+                                    common::Message::Prompt(prompt.clone(), *secret, Some(input))
                                         .into()
-                                    })
-                                    .on_submit(|v| Message::Auth(Some(v)));
+                                })
+                                .on_submit(|v| Message::Auth(Some(v)));
+                        }
 
-                                    if let Some(text_input_id) = self
-                                        .common
-                                        .surface_names
-                                        .get(&id)
-                                        .and_then(|id| self.common.text_input_ids.get(id))
-                                    {
-                                        text_input = text_input.id(text_input_id.clone());
-                                    }
-
-                                    if *secret {
-                                        text_input = text_input.password()
-                                    }
-
-                                    column = column.push(text_input);
-
-                                    if self.common.caps_lock {
-                                        column = column.push(widget::text(fl!("caps-lock")));
-                                    } else if self.common.error_opt.is_none() {
-                                        column = column.push(widget::text(""));
-                                    }
-                                }
-                            }
-                            None => {
-                                // `value_opt == None` is used for non-interactive auth messages
-                                // (e.g. PAM_TEXT_INFO via greetd). This is where fingerprint
-                                // prompts typically come through, so show the message to the user.
-                                column = column.push(widget::text(prompt));
-                            }
+                        if let Some(text_input_id) = self
+                            .common
+                            .surface_names
+                            .get(&id)
+                            .and_then(|id| self.common.text_input_ids.get(id))
+                        {
+                            text_input = text_input.id(text_input_id.clone());
+                        }
+
+                        if *secret {
+                            text_input = text_input.password()
+                        }
+
+                        column = column.push(text_input);
+
+                        if self.common.caps_lock {
+                            column = column.push(widget::text(fl!("caps-lock")));
+                        } else if self.common.error_opt.is_none() {
+                            column = column.push(widget::text(""));
                         }
                     }
                 }
                 SocketState::NotSet => {
-                    column = column.push(widget::text("GREETD_SOCK variable not set"));
+                    column = column
+                        .push(widget::text::heading(fl!("greetd-not-set")))
+                        .push(widget::text(fl!("greetd-not-set-description")));
                 }
-                SocketState::Error(err) => {
-                    column = column.push(widget::text(format!(
-                        "Failed to open GREETD_SOCK: {:?}",
-                        err
-                    )))
+                SocketState::Retrying(err) => {
+                    column = column
+                        .push(
+                            widget::row::with_capacity(2)
+                                .spacing(8.0)
+                                .align_y(Alignment::Center)
+                                .push(widget::indeterminate_circular().size(16.0).bar_height(2.0))
+                                .push(widget::text(fl!("greetd-retrying"))),
+                        )
+                        .push(widget::text::caption(err.to_string()));
                 }
             }
 
+            if let Some(attempts) = self.common.attempts() {
+                column = column.push(attempts);
+            }
+
+            if let (Some(deadline), SocketState::Open) =
+                (&self.autologin_deadline_opt, &self.socket_state)
+            {
+                let seconds = deadline
+                    .saturating_duration_since(Instant::now())
+                    .as_secs_f32()
+                    .ceil();
+                column = column
+                    .push(widget::text(fl!(
+                        "autologin-countdown",
+                        seconds = seconds as u32
+                    )))
+                    .push(widget::text::caption(fl!("autologin-cancel")));
+                column = column.push(
+                    widget::button::suggested(fl!("autologin-now")).on_press(Message::AutologinNow),
+                );
+            }
+
+            if let Some(crash) = &self.flags.session_crash_opt
+                && self
+                    .selected_username
+                    .data_idx
+                    .and_then(|i| self.flags.user_datas.get(i))
+                    .is_some_and(|user_data| user_data.uid == crash.uid.get())
+            {
+                let name = self
+                    .flags
+                    .session(&crash.session, Some(crash.session_type))
+                    .map_or_else(|| crash.session.clone(), Session::label);
+                column = column
+                    .push(widget::text::heading(fl!("session-crashed")))
+                    .push(widget::text::caption(name))
+                    .push(
+                        widget::row::with_capacity(2)
+                            .spacing(8.0)
+                            .push(
+                                widget::button::standard(fl!("session-crashed-retry"))
+                                    .on_press(Message::SessionCrashRetry),
+                            )
+                            .push(
+                                widget::button::standard(fl!("session-crashed-choose"))
+                                    .on_press(Message::SessionCrashChoose),
+                            ),
+                    );
+            } else if let Some(notice) = &self.session_notice_opt {
+                column = column.push(widget::text::caption(notice));
+            }
+
             // Show either authenticating message or error message in the same location
             if self.authenticating {
                 column = column.push(
@@ -947,20 +1220,7 @@ impl App {
         )
         .layer(cosmic::cosmic_theme::Layer::Background)
         .padding(16)
-        .class(cosmic::theme::Container::Custom(Box::new(
-            |theme: &cosmic::Theme| {
-                // Use background appearance as the base
-                let mut appearance =
-                    widget::container::Catalog::style(theme, &cosmic::theme::Container::Background);
-                appearance.background = Some(iced::Background::Color(
-                    // TODO if we can use popups instead of subsurfaces for the greeter and the lockscreen
-                    // then we can allow transparency
-                    theme.cosmic().background(theme.transparent).base.into(),
-                ));
-                appearance.border = iced::Border::default().rounded(16);
-                appearance
-            },
-        )))
+        .class(cosmic::theme::Container::Custom(Box::new(menu_style)))
         .width(Length::Fixed(800.0));
         let menu = if let Some(t) = self.common.rectangle_tracker.as_ref() {
             Element::from(t.container((id, false), menu))
@@ -1035,6 +1295,67 @@ impl App {
         }
     }
 
+    /// Selects the session of the selected user, falling back to the default session with a
+    /// notice when the one they logged into last time is no longer installed
+    fn select_user_session(&mut self) {
+        let data_idx = self.selected_username.data_idx;
+        self.selected_session = self.flags.user_session(data_idx).cloned();
+        self.session_notice_opt = match &self.selected_session {
+            Some(session) => self.flags.missing_last_session(data_idx).map(|missing| {
+                tracing::warn!(
+                    "last session {:?} of {} not found, using {:?}",
+                    missing,
+                    self.selected_username.username,
+                    session.id
+                );
+                fl!(
+                    "session-unavailable",
+                    session = missing,
+                    fallback = session.label()
+                )
+            }),
+            None => Some(fl!("session-none")),
+        };
+    }
+
+    /// Remembers the session being started, to tell on the next start if it ended too quickly
+    fn record_session_start(&self) {
+        let (Some(session), Some(uid)) = (
+            &self.selected_session,
+            self.selected_username
+                .data_idx
+                .and_then(|i| self.flags.user_datas.get(i))
+                .and_then(|user_data| NonZeroU32::new(user_data.uid)),
+        ) else {
+            return;
+        };
+        let Some(handler) = &self.flags.greeter_state_handler else {
+            return;
+        };
+        let start = SessionStart::new(uid, session.id.clone(), session.session_type);
+        if let Err(err) = handler.set("last_session_start", Some(&start)) {
+            tracing::error!("failed to set last session start {:?}: {:?}", start, err);
+        }
+    }
+
+    /// Starts authenticating the selected user
+    fn create_session(&mut self) {
+        if self.entering_name || self.choosing_user {
+            // The session is created when the name is submitted or a user is picked
+            return;
+        }
+        if self.flags.sessions.is_empty() {
+            // Users without a password would be logged in straight away, with no session to
+            // start
+            tracing::error!("no sessions found, not creating greetd session");
+            return;
+        }
+        self.common.reset_prompts();
+        self.send_request(Request::CreateSession {
+            username: self.selected_username.username.clone(),
+        });
+    }
+
     fn update_user_data(&mut self) -> Task<Message> {
         let user_data = match self
             .selected_username
@@ -1047,6 +1368,23 @@ impl App {
             }
         };
 
+        if !self.user_data_loaded.contains(&user_data.uid) {
+            let uid = user_data.uid;
+            return cosmic::task::future(async move {
+                match user_data_dbus(uid).await {
+                    Ok(user_data) => Message::UserData(uid, Some(Box::new(user_data))),
+                    Err(err) => {
+                        tracing::error!(
+                            "failed to load user data for {} from daemon: {}",
+                            uid,
+                            err
+                        );
+                        Message::UserData(uid, None)
+                    }
+                }
+            });
+        }
+
         self.common.update_user_data(user_data);
 
         if let Some(builder) = &user_data.theme_builder_opt {
@@ -1068,6 +1406,78 @@ impl App {
                 theme.clone(),
             ))));
         }
+        tasks.push(self.update_wallpapers());
+
+        Task::batch(tasks)
+    }
+
+    /// Sets wallpapers of the selected user, loading them from the daemon if needed.
+    fn update_wallpapers(&mut self) -> Task<Message> {
+        let Some(user_data) = self
+            .selected_username
+            .data_idx
+            .and_then(|i| self.flags.user_datas.get(i))
+        else {
+            return Task::none();
+        };
+        if !self.user_data_loaded.contains(&user_data.uid) {
+            return Task::none();
+        }
+
+        let uid = user_data.uid;
+        let mut tasks = Vec::new();
+        for surface_id in self.common.surface_ids.values() {
+            if self.common.surface_backgrounds.contains_key(surface_id) {
+                continue;
+            }
+
+            let Some(output_name) = self.common.surface_names.get(surface_id) else {
+                continue;
+            };
+
+            match user_data.bg_source(output_name) {
+                Some(BgSource::Path(_)) => {}
+                Some(BgSource::Color(color)) => {
+                    self.common
+                        .surface_backgrounds
+                        .insert(*surface_id, common::Background::Color(color.clone()));
+                    continue;
+                }
+                None => continue,
+            }
+
+            let key = (uid, output_name.clone());
+            if let Some(image) = self.wallpapers.get(&key) {
+                self.common.surface_backgrounds.insert(
+                    *surface_id,
+                    common::Background::Image(
+                        image.clone(),
+                        user_data.bg_scaling_mode(output_name),
+                    ),
+                );
+                continue;
+            }
+
+            if self.wallpaper_requests.insert(key) {
+                tracing::info!("loading wallpaper for {:?}", output_name);
+                let output_name = output_name.clone();
+                let blur_radius = self.common.wallpaper_blur_radius;
+                tasks.push(cosmic::task::future(async move {
+                    let image_opt = match wallpaper_dbus(uid, &output_name).await {
+                        Ok(bytes) => common::wallpaper_image(bytes, blur_radius).await,
+                        Err(err) => {
+                            tracing::error!(
+                                "output {}: failed to load wallpaper from daemon: {}",
+                                output_name,
+                                err
+                            );
+                            None
+                        }
+                    };
+                    Message::Wallpaper(uid, output_name, image_opt)
+                }));
+            }
+        }
 
         Task::batch(tasks)
     }
@@ -1100,9 +1510,13 @@ impl cosmic::Application for App {
         core.set_app_type(cosmic::core::AppType::System);
         let mut tasks = Vec::new();
         let (mut common, common_task) = Common::init(core);
+        common.wallpaper_blur_radius = flags.greeter_config.wallpaper_blur_radius;
+        common.wallpaper_dim = flags.greeter_config.wallpaper_dim;
+        common.focus_follows_pointer = flags.greeter_config.single_output_prompt;
         common.on_output_event = Some(Box::new(|output_event, output| {
             Message::OutputEvent(output_event, output)
         }));
+        common.on_submit = Some(Box::new(|value| Message::Auth(Some(value))));
         tasks.push(common_task);
 
         //TODO: use full_name?
@@ -1115,35 +1529,31 @@ impl cosmic::Application for App {
 
         let last_user = flags.greeter_config.last_user.as_ref();
 
-        let (username, uid) = last_user
-            .and_then(|last_user| {
-                flags
-                    .user_datas
-                    .iter()
-                    .find(|d| d.uid == last_user.get())
-                    .map(|x| (x.name.clone(), NonZeroU32::new(x.uid)))
-            })
+        let mut autologin_deadline_opt = None;
+        let autologin_user_opt = flags.autologin_opt.as_ref().and_then(|autologin| {
+            if flags.user_datas.iter().any(|d| d.name == autologin.user) {
+                autologin_deadline_opt =
+                    Some(Instant::now() + Duration::from_secs(autologin.delay.into()));
+                Some(autologin.user.clone())
+            } else {
+                tracing::warn!("autologin user {:?} not found", autologin.user);
+                None
+            }
+        });
+
+        let username = autologin_user_opt
             .or_else(|| {
-                flags
-                    .user_datas
-                    .first()
-                    .map(|x| (x.name.clone(), NonZeroU32::new(x.uid)))
+                last_user.and_then(|last_user| {
+                    flags
+                        .user_datas
+                        .iter()
+                        .find(|d| d.uid == last_user.get())
+                        .map(|x| x.name.clone())
+                })
             })
+            .or_else(|| flags.user_datas.first().map(|x| x.name.clone()))
             .unwrap_or_default();
 
-        let mut session_names: Vec<_> = flags.sessions.keys().map(|x| x.to_string()).collect();
-        session_names.sort();
-
-        let selected_session = uid
-            .and_then(|uid| {
-                flags
-                    .greeter_config
-                    .users
-                    .get(&uid)
-                    .and_then(|user| user.last_session.clone())
-            })
-            .or_else(|| session_names.first().cloned())
-            .unwrap_or_default();
         let data_idx = flags.user_datas.iter().position(|d| d.name == username);
         let selected_username = NameIndexPair { username, data_idx };
         let accessibility = Accessibility {
@@ -1152,25 +1562,47 @@ impl cosmic::Application for App {
             ..Default::default()
         };
 
-        let app = App {
+        let mut app = App {
             common,
             flags,
             greetd_sender: None,
             socket_state: SocketState::Pending,
             usernames,
             selected_username,
-            session_names,
-            selected_session,
+            user_data_loaded: HashSet::new(),
+            wallpapers: HashMap::new(),
+            wallpaper_requests: HashSet::new(),
+            selected_session: None,
+            session_chosen: false,
+            session_notice_opt: None,
+            autologin_deadline_opt,
             dialog_page_opt: None,
             dropdown_opt: None,
             heartbeat_handle: None,
             entering_name: false,
+            choosing_user: false,
             accessibility,
             theme_builder: Default::default(),
             randr_list: None,
             surface_id_pairs: Vec::new(),
             authenticating: false,
         };
+        app.select_user_session();
+        if app.autologin_deadline_opt.is_some()
+            && let Some(id) = app
+                .flags
+                .autologin_opt
+                .as_ref()
+                .and_then(|autologin| autologin.session.as_deref())
+        {
+            match app.flags.session(id, None) {
+                Some(session) => {
+                    app.selected_session = Some(session.clone());
+                    app.session_notice_opt = None;
+                }
+                None => tracing::warn!("autologin session {:?} not found", id),
+            }
+        }
         (app, Task::batch(tasks))
     }
 
@@ -1182,9 +1614,15 @@ impl cosmic::Application for App {
                 // sending PostAuthMessageResponse. For non-interactive "info" messages
                 // (fingerprint prompts typically come through here), the correct response
                 // is `None`. If we don't ACK, greetd will wait forever and the UI will
-                // appear "stuck" on the last info message.
-                if let common::Message::Prompt(_, _secret, None) = &common_message {
-                    self.send_request(Request::PostAuthMessageResponse { response: None });
+                // appear "stuck" on the last info message. Errors are acknowledged the same way,
+                // for example when a new password is rejected and PAM asks again.
+                if let common::Message::AuthPrompt(kind, _) = &common_message {
+                    if kind.is_input() {
+                        // The previous answer was accepted, allow input again
+                        self.authenticating = false;
+                    } else {
+                        self.send_request(Request::PostAuthMessageResponse { response: None });
+                    }
                 }
 
                 return self.common.update(common_message);
@@ -1224,7 +1662,7 @@ impl cosmic::Application for App {
                                     self.common
                                         .surface_names
                                         .insert(subsurface_id, output_name.clone());
-                                    self.common.surface_images.remove(&surface_id);
+                                    self.common.surface_backgrounds.remove(&surface_id);
                                     let text_input_id =
                                         widget::Id::new(format!("input-{output_name}",));
                                     self.common
@@ -1309,7 +1747,7 @@ impl cosmic::Application for App {
                         tracing::info!("output {}: removed", output.id());
                         match self.common.surface_ids.remove(&output) {
                             Some(surface_id) => {
-                                self.common.surface_images.remove(&surface_id);
+                                self.common.surface_backgrounds.remove(&surface_id);
                                 self.common.window_size.remove(&surface_id);
                                 if let Some(n) = self.common.surface_names.remove(&surface_id) {
                                     self.common.text_input_ids.remove(&n);
@@ -1329,17 +1767,24 @@ impl cosmic::Application for App {
             Message::Socket(socket_state) => {
                 self.socket_state = socket_state;
                 if let SocketState::Open = &self.socket_state {
-                    // When socket is opened, send create session
-                    self.send_request(Request::CreateSession {
-                        username: self.selected_username.username.clone(),
-                    });
+                    match self.autologin_deadline_opt {
+                        // When socket is opened, send create session
+                        None => self.create_session(),
+                        Some(deadline) if deadline <= Instant::now() => {
+                            return self.update(Message::AutologinNow);
+                        }
+                        // The autologin session is created when the countdown ends
+                        Some(_) => {}
+                    }
                 }
             }
             Message::Reload(new) => {
                 return cosmic::command::set_theme(new.clone());
             }
-            Message::Session(selected_session) => {
-                self.selected_session = selected_session;
+            Message::Session(session) => {
+                self.selected_session = Some(session);
+                self.session_chosen = true;
+                self.session_notice_opt = None;
                 if self.dropdown_opt == Some(Dropdown::Session) {
                     self.dropdown_opt = None;
                     return self.common.dropdown_blur_rects(false);
@@ -1350,6 +1795,7 @@ impl cosmic::Application for App {
                     self.dropdown_opt = None;
                 }
                 self.entering_name = true;
+                self.choosing_user = false;
                 self.selected_username = NameIndexPair {
                     data_idx: self
                         .flags
@@ -1369,8 +1815,12 @@ impl cosmic::Application for App {
                 if self.dropdown_opt == Some(Dropdown::User) {
                     self.dropdown_opt = None;
                 }
-                if self.entering_name || username != self.selected_username.username {
+                if self.entering_name
+                    || self.choosing_user
+                    || username != self.selected_username.username
+                {
                     self.entering_name = false;
+                    self.choosing_user = false;
                     self.authenticating = false;
                     let data_idx = self
                         .flags
@@ -1378,25 +1828,16 @@ impl cosmic::Application for App {
                         .iter()
                         .position(|d| d.name == username);
                     self.selected_username = NameIndexPair { username, data_idx };
-                    self.common.surface_images.clear();
-                    if let Some(session) = data_idx.and_then(|i| {
-                        self.flags
-                            .user_datas
-                            .get(i)
-                            .and_then(|UserData { uid, .. }| {
-                                NonZeroU32::new(*uid).and_then(|uid| {
-                                    self.flags
-                                        .greeter_config
-                                        .users
-                                        .get(&uid)
-                                        .and_then(|conf| conf.last_session.as_deref())
-                                })
-                            })
-                    }) {
-                        session.clone_into(&mut self.selected_session);
-                    };
+                    self.common.surface_backgrounds.clear();
+                    // Failed attempts and lockouts are counted per user
+                    self.common.failed_attempts = 0;
+                    self.common.lockout_until_opt = None;
+                    // Picking another user overrides autologin and the chosen session
+                    self.autologin_deadline_opt = None;
+                    self.session_chosen = false;
+                    self.select_user_session();
                     if let SocketState::Open = &self.socket_state {
-                        self.common.prompt_opt = None;
+                        self.common.reset_prompts();
                         self.send_request(Request::CancelSession);
                     }
                     if let Some(randr_list) = self.randr_list.as_ref() {
@@ -1445,28 +1886,23 @@ impl cosmic::Application for App {
                         err
                     );
                 }
+                let Some(session) = &self.selected_session else {
+                    return Task::none();
+                };
+                let user_state = UserState {
+                    uid,
+                    last_session: Some(session.id.clone()),
+                    last_session_type: Some(session.session_type),
+                };
                 match user_entry {
                     hash_map::Entry::Vacant(entry) => {
-                        let last_session = Some(self.selected_session.clone());
-                        entry.insert(cosmic_greeter_config::user::UserState { uid, last_session });
+                        entry.insert(user_state);
                     }
                     hash_map::Entry::Occupied(mut entry) => {
-                        let last_session = entry.get_mut().last_session.as_mut();
-                        if last_session
-                            .as_ref()
-                            .is_some_and(|session| session.as_str() == self.selected_session)
-                        {
+                        if entry.get() == &user_state {
                             return Task::none();
                         }
-                        if let Some(session) = last_session {
-                            self.selected_session.clone_into(session);
-                        } else {
-                            let last_session = Some(self.selected_session.clone());
-                            entry.insert(cosmic_greeter_config::user::UserState {
-                                uid,
-                                last_session,
-                            });
-                        }
+                        entry.insert(user_state);
                     }
                 }
 
@@ -1478,7 +1914,7 @@ impl cosmic::Application for App {
                 if let Err(err) = handler.set("users", &self.flags.greeter_config.users) {
                     tracing::error!(
                         "Failed to set {} as last selected session for {} (UID: {}): {:?}",
-                        self.selected_session,
+                        session.id,
                         self.selected_username.username,
                         uid,
                         err
@@ -1486,24 +1922,104 @@ impl cosmic::Application for App {
                 }
             }
             Message::Auth(response) => {
-                if response.as_deref() == Some("") {
+                if response.as_deref() == Some("") || self.common.lockout_remaining().is_some() {
                     return Task::none();
                 }
                 self.common.error_opt = None;
+                if let Some(value) = &response
+                    && !self.common.check_submit(value)
+                {
+                    return Task::none();
+                }
                 self.authenticating = true;
                 self.send_request(Request::PostAuthMessageResponse { response });
             }
+            Message::UserData(uid, user_data_opt) => {
+                self.user_data_loaded.insert(uid);
+                if let Some(mut user_data) = user_data_opt
+                    && let Some(i) = self.flags.user_datas.iter().position(|d| d.uid == uid)
+                {
+                    self.flags.user_icons[i] = user_data
+                        .icon_opt
+                        .take()
+                        .map(widget::image::Handle::from_bytes);
+                    self.flags.user_datas[i] = *user_data;
+                }
+                let data_idx = self.selected_username.data_idx;
+                if data_idx
+                    .and_then(|i| self.flags.user_datas.get(i))
+                    .is_some_and(|d| d.uid == uid)
+                {
+                    // The session set in AccountsService is known now, the autologin session
+                    // and a session chosen by the user are kept
+                    let autologin_session = self.autologin_deadline_opt.is_some()
+                        && self
+                            .flags
+                            .autologin_opt
+                            .as_ref()
+                            .is_some_and(|autologin| autologin.session.is_some());
+                    if !autologin_session
+                        && !self.session_chosen
+                        && self
+                            .flags
+                            .user_state(data_idx)
+                            .is_none_or(|state| state.last_session.is_none())
+                    {
+                        self.select_user_session();
+                    }
+                    self.common.surface_backgrounds.clear();
+                    return self.update_user_data();
+                }
+            }
+            Message::UserDataChanged(uid) => {
+                // Reload the user data, and wallpapers that may have changed with it
+                self.user_data_loaded.remove(&uid);
+                self.wallpapers
+                    .retain(|(wallpaper_uid, _), _| *wallpaper_uid != uid);
+                self.wallpaper_requests
+                    .retain(|(request_uid, _)| *request_uid != uid);
+                return cosmic::task::future(async move {
+                    match user_data_dbus(uid).await {
+                        Ok(user_data) => Message::UserData(uid, Some(Box::new(user_data))),
+                        Err(err) => {
+                            tracing::error!(
+                                "failed to reload user data for {} from daemon: {}",
+                                uid,
+                                err
+                            );
+                            Message::UserData(uid, None)
+                        }
+                    }
+                });
+            }
+            Message::Wallpaper(uid, output_name, image_opt) => {
+                if let Some(image) = image_opt {
+                    self.wallpapers.insert((uid, output_name), image);
+                }
+                return self.update_wallpapers();
+            }
             Message::Login => {
-                self.common.prompt_opt = None;
+                self.common.reset_prompts();
                 self.common.error_opt = None;
                 self.authenticating = false;
 
-                match self.flags.sessions.get(&self.selected_session).cloned() {
-                    Some((cmd, env)) => {
-                        self.send_request(Request::StartSession { cmd, env });
+                match self.selected_session.clone() {
+                    Some(session) => {
+                        self.send_request(Request::StartSession {
+                            cmd: session.command,
+                            env: session.env,
+                        });
                         return self.update(Message::ConfigUpdateUser);
                     }
-                    None => todo!("session {:?} not found", self.selected_session),
+                    None => {
+                        tracing::error!(
+                            "no session available for {}",
+                            self.selected_username.username
+                        );
+                        self.common.error_opt = Some(fl!("session-none"));
+                        // Cancel so that greetd does not keep a session waiting to be started
+                        self.send_request(Request::CancelSession);
+                    }
                 }
             }
             Message::Error(error) => {
@@ -1564,6 +2080,65 @@ impl cosmic::Application for App {
                     return self.common.dropdown_blur_rects(true);
                 }
             }
+            Message::SessionCrashRetry => {
+                if let Some(crash) = self.flags.session_crash_opt.take()
+                    && let Some(session) =
+                        self.flags.session(&crash.session, Some(crash.session_type))
+                {
+                    self.selected_session = Some(session.clone());
+                    self.session_chosen = true;
+                    self.session_notice_opt = None;
+                }
+            }
+            Message::SessionCrashChoose => {
+                self.flags.session_crash_opt = None;
+                if self.dropdown_opt != Some(Dropdown::Session) {
+                    return self.update(Message::DropdownToggle(Dropdown::Session));
+                }
+            }
+            Message::AutologinTick => {
+                if self
+                    .autologin_deadline_opt
+                    .is_some_and(|deadline| deadline <= Instant::now())
+                {
+                    return self.update(Message::AutologinNow);
+                }
+            }
+            Message::AutologinCancel => {
+                if self.autologin_deadline_opt.take().is_some() {
+                    tracing::info!("autologin cancelled");
+                    // Authenticating the autologin user would log them in without a password,
+                    // fall back to the last user or let the user pick one
+                    let last_user_opt = self
+                        .flags
+                        .greeter_config
+                        .last_user
+                        .and_then(|uid| self.flags.user_datas.iter().find(|d| d.uid == uid.get()))
+                        .map(|d| d.name.clone())
+                        .filter(|name| name != &self.selected_username.username);
+                    if let Some(last_user) = last_user_opt {
+                        return self.update(Message::Username(last_user));
+                    }
+                    self.choosing_user = true;
+                    if self.dropdown_opt != Some(Dropdown::User) {
+                        return self.update(Message::DropdownToggle(Dropdown::User));
+                    }
+                }
+            }
+            Message::AutologinNow => {
+                // Once the socket is open, the session is created right away
+                if self.autologin_deadline_opt.is_some()
+                    && let SocketState::Open = &self.socket_state
+                {
+                    // Autologin happens once per boot, ask for a password if that can't be
+                    // recorded
+                    if !create_autologin_marker() {
+                        return self.update(Message::AutologinCancel);
+                    }
+                    self.autologin_deadline_opt = None;
+                    self.create_session();
+                }
+            }
             Message::KeyboardLayout(layout_i) => {
                 if let Some(keyboard_layout) = &self.common.keyboard_layout {
                     keyboard_layout.set_group(layout_i as u32);
@@ -1627,9 +2202,10 @@ impl cosmic::Application for App {
                 None => {}
             },
             Message::Exit => {
+                self.record_session_start();
                 let mut commands = Vec::new();
                 for (_output, surface_id) in self.common.surface_ids.drain() {
-                    self.common.surface_images.remove(&surface_id);
+                    self.common.surface_backgrounds.remove(&surface_id);
                     self.common.surface_names.remove(&surface_id);
                     if let Some(n) = self.common.surface_names.remove(&surface_id) {
                         self.common.text_input_ids.remove(&n);
@@ -1861,22 +2437,14 @@ impl cosmic::Application for App {
 
     /// Creates a view after each update.
     fn view_window(&self, surface_id: SurfaceId) -> Element<'_, Self::Message> {
-        let img = self
-            .common
-            .surface_images
-            .get(&surface_id)
-            .unwrap_or(&self.common.fallback_background);
-        widget::image(img)
-            .content_fit(iced::ContentFit::Cover)
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .into()
+        self.common.background(surface_id)
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
-        Subscription::batch([
+        let mut subscriptions = vec![
             self.common.subscription().map(Message::from),
             ipc::subscription(),
+            user_data_changed_subscription(),
             wayland::a11y_subscription().map(Message::WaylandUpdate),
             listen_with(|event, _status, id| match event {
                 iced::Event::Window(window::Event::Resized(size))
@@ -1885,10 +2453,34 @@ impl cosmic::Application for App {
                 }
                 _ => None,
             }),
-        ])
+        ];
+        if self.autologin_deadline_opt.is_some() {
+            subscriptions
+                .push(iced::time::every(Duration::from_secs(1)).map(|_| Message::AutologinTick));
+            subscriptions.push(listen_with(|event, _status, _id| match event {
+                iced::Event::Keyboard(iced::keyboard::Event::KeyPressed { .. }) => {
+                    Some(Message::AutologinCancel)
+                }
+                _ => None,
+            }));
+        }
+        Subscription::batch(subscriptions)
     }
 }
 
+fn menu_style(theme: &cosmic::Theme) -> widget::container::Style {
+    // Use background appearance as the base
+    let mut appearance =
+        widget::container::Catalog::style(theme, &cosmic::theme::Container::Background);
+    appearance.background = Some(iced::Background::Color(
+        // TODO if we can use popups instead of subsurfaces for the greeter and the lockscreen
+        // then we can allow transparency
+        theme.cosmic().background(theme.transparent).base.into(),
+    ));
+    appearance.border = iced::Border::default().rounded(16);
+    appearance
+}
+
 pub fn apply_hc_theme(
     builder: cosmic_theme::ThemeBuilder,
     enabled: bool,
@@ -3,35 +3,108 @@
 
 use super::{Message, SocketState};
 use cosmic::iced::Subscription;
+use cosmic::iced::futures::channel::mpsc::Sender;
 use futures_util::SinkExt;
 use greetd_ipc::codec::TokioCodec;
+use greetd_ipc::{AuthMessageType, Request, Response};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::UnixStream;
 use tokio::sync::mpsc;
 
-use crate::{common, fl};
+use crate::common;
 
-/// Convert greetd error descriptions to user-friendly localized messages
-fn greetd_error_to_message(error_type: greetd_ipc::ErrorType, description: &str) -> String {
-    use greetd_ipc::ErrorType;
+/// Delay before the first reconnection attempt, doubled after every failed attempt
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+/// Longest delay between reconnection attempts
+const RECONNECT_MAX: Duration = Duration::from_secs(30);
+
+/// Converts the response of greetd to a request into messages for the greeter. Returns `None`
+/// when the connection has to be reopened.
+fn response_messages(request: &Request, response: Response) -> Option<Vec<Message>> {
+    match response {
+        Response::AuthMessage {
+            auth_message_type,
+            auth_message,
+        } => {
+            let kind = match auth_message_type {
+                AuthMessageType::Secret => common::PromptKind::Secret,
+                AuthMessageType::Visible => common::PromptKind::Visible,
+                AuthMessageType::Info => common::PromptKind::Info,
+                AuthMessageType::Error => common::PromptKind::Error,
+            };
+            Some(vec![common::Message::AuthPrompt(kind, auth_message).into()])
+        }
+        Response::Error {
+            error_type,
+            description,
+        } => match request {
+            Request::CancelSession => {
+                // Do not send errors for cancel session to gui
+                tracing::warn!("error while cancelling session: {}", description);
 
-    match error_type {
-        ErrorType::AuthError => {
-            // For authentication errors, check description for specific error types
-            if description.contains("PERM_DENIED") {
-                fl!("auth-error-denied")
-            } else if description.contains("MAXTRIES") {
-                fl!("auth-error-maxtries")
-            } else if description.contains("ACCT_EXPIRED") || description.contains("USER_UNKNOWN") {
-                fl!("auth-error-account")
-            } else {
-                fl!("auth-error-credentials")
+                // Reconnect to socket
+                None
+            }
+            _ => {
+                let error = common::AuthError::from_greetd(&error_type, &description);
+                error.log(&description);
+                let mut messages = Vec::with_capacity(2);
+                if error == common::AuthError::Credentials {
+                    messages.push(common::Message::AuthFailed.into());
+                }
+                messages.push(Message::Error(error.message()));
+                Some(messages)
+            }
+        },
+        Response::Success => match request {
+            Request::CreateSession { .. } => {
+                // User has no auth required, proceed to login
+                Some(vec![Message::Login])
+            }
+            Request::PostAuthMessageResponse { .. } => {
+                // All auth is completed, proceed to login
+                Some(vec![Message::Login])
+            }
+            Request::StartSession { .. } => {
+                // Session has been started, exit greeter
+                Some(vec![Message::Exit])
+            }
+            Request::CancelSession => {
+                tracing::info!("greetd IPC session canceled");
+                // Reconnect to socket
+                None
             }
+        },
+    }
+}
+
+/// Sends requests to greetd one at a time, and their responses as messages to the greeter.
+/// Returns when the connection has to be reopened, or when there will be no more requests.
+pub async fn run_connection(
+    stream: &mut UnixStream,
+    rx: &mut mpsc::Receiver<Request>,
+    sender: &mut Sender<Message>,
+) {
+    while let Some(request) = rx.recv().await {
+        if let Err(why) = request.write_to(stream).await {
+            tracing::error!("error writing to GREETD_SOCK stream: {why:?}");
+            return;
         }
-        ErrorType::Error => {
-            // For generic errors, show a generic message
-            fl!("auth-error-default")
+
+        let response = match Response::read_from(stream).await {
+            Ok(response) => response,
+            Err(err) => {
+                tracing::error!("failed to read socket: {:?}", err);
+                return;
+            }
+        };
+
+        let Some(messages) = response_messages(&request, response) else {
+            return;
+        };
+        for message in messages {
+            _ = sender.send(message).await;
         }
     }
 }
@@ -39,144 +112,330 @@ fn greetd_error_to_message(error_type: greetd_ipc::ErrorType, description: &str)
 pub fn subscription() -> Subscription<Message> {
     struct GreetdSubscription;
     Subscription::run_with(std::any::TypeId::of::<GreetdSubscription>(), |_| {
-        cosmic::iced::stream::channel(
-            1,
-            |mut sender: cosmic::iced::futures::channel::mpsc::Sender<_>| async move {
-                let (tx, mut rx) = mpsc::channel::<greetd_ipc::Request>(1);
-                _ = sender.send(Message::GreetdChannel(tx)).await;
-
-                let socket_path =
-                    std::env::var_os("GREETD_SOCK").expect("GREETD_SOCK environment not set");
-
-                let mut interval = tokio::time::interval(Duration::from_secs(1));
-
-                loop {
-                    _ = sender.send(Message::Reconnect).await;
-
-                    let mut stream = match UnixStream::connect(&socket_path).await {
-                        Ok(stream) => stream,
-                        Err(why) => {
-                            tracing::error!("greetd IPC socket connection failed: {why:?}");
-                            _ = sender.send(Message::Socket(SocketState::Error(Arc::new(why))));
-
-                            break;
-                        }
-                    };
-
-                    _ = sender.send(Message::Socket(SocketState::Open)).await;
-
-                    while let Some(request) = rx.recv().await {
-                        if let Err(why) = request.write_to(&mut stream).await {
-                            tracing::error!("error writing to GREETD_SOCK stream: {why:?}");
-                            break;
-                        }
-
-                        match greetd_ipc::Response::read_from(&mut stream).await {
-                            Ok(response) => {
-                                match response {
-                                    greetd_ipc::Response::AuthMessage {
-                                        auth_message_type,
-                                        auth_message,
-                                    } => match auth_message_type {
-                                        greetd_ipc::AuthMessageType::Secret => {
-                                            _ = sender
-                                                .send(
-                                                    common::Message::Prompt(
-                                                        auth_message,
-                                                        true,
-                                                        Some(String::new()),
-                                                    )
-                                                    .into(),
-                                                )
-                                                .await;
-                                        }
-                                        greetd_ipc::AuthMessageType::Visible => {
-                                            _ = sender
-                                                .send(
-                                                    common::Message::Prompt(
-                                                        auth_message,
-                                                        false,
-                                                        Some(String::new()),
-                                                    )
-                                                    .into(),
-                                                )
-                                                .await;
-                                        }
-                                        greetd_ipc::AuthMessageType::Info => {
-                                            _ = sender
-                                                .send(
-                                                    common::Message::Prompt(
-                                                        auth_message,
-                                                        false,
-                                                        None,
-                                                    )
-                                                    .into(),
-                                                )
-                                                .await;
-                                        }
-                                        greetd_ipc::AuthMessageType::Error => {
-                                            _ = sender.send(Message::Error(auth_message)).await;
-                                        }
-                                    },
-                                    greetd_ipc::Response::Error {
-                                        error_type,
-                                        description,
-                                    } => {
-                                        match request {
-                                            greetd_ipc::Request::CancelSession => {
-                                                // Do not send errors for cancel session to gui
-                                                tracing::warn!(
-                                                    "error while cancelling session: {}",
-                                                    description
-                                                );
-
-                                                // Reconnect to socket
-                                                break;
-                                            }
-                                            _ => {
-                                                _ = sender
-                                                    .send(Message::Error(greetd_error_to_message(
-                                                        error_type,
-                                                        &description,
-                                                    )))
-                                                    .await;
-                                            }
-                                        }
-                                    }
-                                    greetd_ipc::Response::Success => match request {
-                                        greetd_ipc::Request::CreateSession { .. } => {
-                                            // User has no auth required, proceed to login
-                                            _ = sender.send(Message::Login).await;
-                                        }
-                                        greetd_ipc::Request::PostAuthMessageResponse { .. } => {
-                                            // All auth is completed, proceed to login
-                                            _ = sender.send(Message::Login).await;
-                                        }
-                                        greetd_ipc::Request::StartSession { .. } => {
-                                            // Session has been started, exit greeter
-                                            _ = sender.send(Message::Exit).await;
-                                        }
-                                        greetd_ipc::Request::CancelSession => {
-                                            tracing::info!("greetd IPC session canceled");
-                                            // Reconnect to socket
-                                            break;
-                                        }
-                                    },
-                                }
-                            }
-                            Err(err) => {
-                                tracing::error!("failed to read socket: {:?}", err);
-                                break;
-                            }
-                        }
+        cosmic::iced::stream::channel(1, |mut sender: Sender<_>| async move {
+            let (tx, mut rx) = mpsc::channel::<Request>(1);
+            _ = sender.send(Message::GreetdChannel(tx)).await;
+
+            let Some(socket_path) = std::env::var_os("GREETD_SOCK") else {
+                tracing::error!("GREETD_SOCK environment not set");
+                _ = sender.send(Message::Socket(SocketState::NotSet)).await;
+                return futures_util::future::pending().await;
+            };
+
+            let mut delay = RECONNECT_MIN;
+
+            loop {
+                _ = sender.send(Message::Reconnect).await;
+
+                let mut stream = match UnixStream::connect(&socket_path).await {
+                    Ok(stream) => stream,
+                    Err(why) => {
+                        // greetd may be restarting, try again later
+                        tracing::error!(
+                            "greetd IPC socket connection failed, retrying in {delay:?}: {why:?}"
+                        );
+                        _ = sender
+                            .send(Message::Socket(SocketState::Retrying(Arc::new(why))))
+                            .await;
+                        tokio::time::sleep(delay).await;
+                        delay = (delay * 2).min(RECONNECT_MAX);
+                        continue;
                     }
+                };
+                delay = RECONNECT_MIN;
 
-                    tracing::info!("reconnecting to greetd IPC socket");
-                    interval.tick().await;
-                }
+                // Drop requests made for the previous connection
+                while rx.try_recv().is_ok() {}
 
-                futures_util::future::pending().await
-            },
-        )
+                _ = sender.send(Message::Socket(SocketState::Open)).await;
+
+                run_connection(&mut stream, &mut rx, &mut sender).await;
+
+                tracing::info!("reconnecting to greetd IPC socket");
+                tokio::time::sleep(delay).await;
+            }
+        })
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmic::iced::futures::StreamExt;
+    use greetd_ipc::ErrorType;
+    use tokio::net::UnixListener;
+
+    /// Runs requests against a mock greetd that answers with the given responses, returning the
+    /// requests greetd received and the messages sent to the greeter
+    async fn scenario(
+        name: &str,
+        requests: Vec<Request>,
+        responses: Vec<Response>,
+    ) -> (Vec<Request>, Vec<Message>) {
+        crate::localize::localizer();
+
+        let path = std::env::temp_dir().join(format!(
+            "cosmic-greeter-ipc-{}-{}.sock",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut socket, _addr) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            for response in responses {
+                received.push(Request::read_from(&mut socket).await.unwrap());
+                response.write_to(&mut socket).await.unwrap();
+            }
+            received
+        });
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        let (tx, mut rx) = mpsc::channel(requests.len().max(1));
+        for request in requests {
+            tx.send(request).await.unwrap();
+        }
+        drop(tx);
+
+        let (mut sender, receiver) = cosmic::iced::futures::channel::mpsc::channel(16);
+        run_connection(&mut stream, &mut rx, &mut sender).await;
+        drop(sender);
+        let messages = receiver.collect().await;
+
+        let received = server.await.unwrap();
+        let _ = std::fs::remove_file(&path);
+        (received, messages)
+    }
+
+    fn create_session() -> Request {
+        Request::CreateSession {
+            username: "user".to_string(),
+        }
+    }
+
+    fn respond(response: &str) -> Request {
+        Request::PostAuthMessageResponse {
+            response: Some(response.to_string()),
+        }
+    }
+
+    /// Info messages are acknowledged without a response
+    fn acknowledge() -> Request {
+        Request::PostAuthMessageResponse { response: None }
+    }
+
+    fn start_session() -> Request {
+        Request::StartSession {
+            cmd: vec!["cosmic-session".to_string()],
+            env: Vec::new(),
+        }
+    }
+
+    fn prompt(auth_message_type: AuthMessageType, auth_message: &str) -> Response {
+        Response::AuthMessage {
+            auth_message_type,
+            auth_message: auth_message.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn no_auth() {
+        let (received, messages) = scenario(
+            "no-auth",
+            vec![create_session(), start_session()],
+            vec![Response::Success, Response::Success],
+        )
+        .await;
+        assert!(
+            matches!(
+                received.as_slice(),
+                [Request::CreateSession { .. }, Request::StartSession { .. }]
+            ),
+            "{received:?}"
+        );
+        assert!(
+            matches!(messages.as_slice(), [Message::Login, Message::Exit]),
+            "{messages:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn password() {
+        let (received, messages) = scenario(
+            "password",
+            vec![create_session(), respond("password")],
+            vec![
+                prompt(AuthMessageType::Secret, "Password:"),
+                Response::Success,
+            ],
+        )
+        .await;
+        assert!(
+            matches!(
+                received.as_slice(),
+                [
+                    Request::CreateSession { .. },
+                    Request::PostAuthMessageResponse { response: Some(response) },
+                ] if response == "password"
+            ),
+            "{received:?}"
+        );
+        assert!(
+            matches!(
+                messages.as_slice(),
+                [
+                    Message::Common(common::Message::AuthPrompt(
+                        common::PromptKind::Secret,
+                        prompt
+                    )),
+                    Message::Login,
+                ] if prompt == "Password:"
+            ),
+            "{messages:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn two_factor() {
+        let (received, messages) = scenario(
+            "two-factor",
+            vec![create_session(), respond("password"), acknowledge()],
+            vec![
+                prompt(AuthMessageType::Secret, "Password:"),
+                prompt(AuthMessageType::Info, "Check your phone"),
+                Response::Success,
+            ],
+        )
+        .await;
+        assert!(
+            matches!(
+                received.as_slice(),
+                [
+                    Request::CreateSession { .. },
+                    Request::PostAuthMessageResponse { response: Some(_) },
+                    Request::PostAuthMessageResponse { response: None },
+                ]
+            ),
+            "{received:?}"
+        );
+        assert!(
+            matches!(
+                messages.as_slice(),
+                [
+                    Message::Common(common::Message::AuthPrompt(common::PromptKind::Secret, _)),
+                    Message::Common(common::Message::AuthPrompt(common::PromptKind::Info, _)),
+                    Message::Login,
+                ]
+            ),
+            "{messages:?}"
+        );
+
+        let (received, messages) = scenario(
+            "two-factor-code",
+            vec![create_session(), respond("password"), respond("123456")],
+            vec![
+                prompt(AuthMessageType::Secret, "Password:"),
+                prompt(AuthMessageType::Visible, "Verification code:"),
+                Response::Success,
+            ],
+        )
+        .await;
+        assert!(
+            matches!(
+                received.as_slice(),
+                [
+                    Request::CreateSession { .. },
+                    Request::PostAuthMessageResponse { response: Some(_) },
+                    Request::PostAuthMessageResponse { response: Some(code) },
+                ] if code == "123456"
+            ),
+            "{received:?}"
+        );
+        assert!(
+            matches!(
+                messages.as_slice(),
+                [
+                    Message::Common(common::Message::AuthPrompt(common::PromptKind::Secret, _)),
+                    Message::Common(common::Message::AuthPrompt(common::PromptKind::Visible, _)),
+                    Message::Login,
+                ]
+            ),
+            "{messages:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn wrong_password() {
+        let (_received, messages) = scenario(
+            "wrong-password",
+            vec![create_session(), respond("wrong")],
+            vec![
+                prompt(AuthMessageType::Secret, "Password:"),
+                Response::Error {
+                    error_type: ErrorType::AuthError,
+                    description: "AUTH_ERR".to_string(),
+                },
+            ],
+        )
+        .await;
+        let expected = common::AuthError::Credentials.message();
+        assert!(
+            matches!(
+                messages.as_slice(),
+                [
+                    Message::Common(common::Message::AuthPrompt(
+                        common::PromptKind::Secret,
+                        _
+                    )),
+                    Message::Common(common::Message::AuthFailed),
+                    Message::Error(error),
+                ] if *error == expected
+            ),
+            "{messages:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn cancel_error() {
+        // The connection is reopened without showing the error, later requests are not sent
+        let (received, messages) = scenario(
+            "cancel-error",
+            vec![Request::CancelSession, create_session()],
+            vec![Response::Error {
+                error_type: ErrorType::Error,
+                description: "no session active".to_string(),
+            }],
+        )
+        .await;
+        assert!(
+            matches!(received.as_slice(), [Request::CancelSession]),
+            "{received:?}"
+        );
+        assert!(messages.is_empty(), "{messages:?}");
+    }
+
+    #[tokio::test]
+    async fn start_session_error() {
+        let (_received, messages) = scenario(
+            "start-session-error",
+            vec![create_session(), start_session()],
+            vec![
+                Response::Success,
+                Response::Error {
+                    error_type: ErrorType::Error,
+                    description: "could not start session".to_string(),
+                },
+            ],
+        )
+        .await;
+        let expected = common::AuthError::Other.message();
+        assert!(
+            matches!(
+                messages.as_slice(),
+                [Message::Login, Message::Error(error)] if *error == expected
+            ),
+            "{messages:?}"
+        );
+    }
+}
@@ -0,0 +1,544 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic::desktop::fde::{DesktopEntry, get_languages_from_env};
+use cosmic_greeter_config::Config;
+use cosmic_greeter_config::user::SessionType;
+use std::collections::HashSet;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::{env, fs};
+
+use crate::fl;
+
+/// A session read from a desktop file in `wayland-sessions` or `xsessions`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Session {
+    /// Desktop file id, the file name without the `.desktop` extension
+    pub id: String,
+    pub name: String,
+    pub comment_opt: Option<String>,
+    pub icon_opt: Option<String>,
+    pub session_type: SessionType,
+    pub command: Vec<String>,
+    pub env: Vec<String>,
+}
+
+impl Session {
+    /// Name shown in the greeter, X11 sessions are marked so they can be told apart from
+    /// Wayland sessions with the same name
+    pub fn label(&self) -> String {
+        match self.session_type {
+            SessionType::Wayland => self.name.clone(),
+            SessionType::X11 => fl!("session-x11", name = self.name.as_str()),
+        }
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Checks a `TryExec` value, which is either an absolute path or a program searched in `PATH`
+fn try_exec(program: &str) -> bool {
+    let path = Path::new(program);
+    if path.is_absolute() {
+        return is_executable(path);
+    }
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| is_executable(&dir.join(program))))
+}
+
+/// X11 sessions are started with xinit, which needs an X server
+fn x11_available() -> bool {
+    try_exec("xinit") && (try_exec("Xorg") || try_exec("X"))
+}
+
+/// Builds session commands around the exec line of sessions
+struct Launcher {
+    x11_launcher_opt: Option<String>,
+    wrapper: Vec<String>,
+}
+
+impl Launcher {
+    /// Uses the session wrapper of the config, X11 sessions are only started with a launcher
+    fn new(config: &Config, x11_launcher_opt: Option<String>) -> Self {
+        let wrapper = match &config.session_wrapper {
+            // xinit only runs clients given as an absolute path
+            Some(session_wrapper) => shlex::split(session_wrapper)
+                .filter(|wrapper| {
+                    wrapper
+                        .first()
+                        .is_some_and(|arg| Path::new(arg).is_absolute())
+                })
+                .unwrap_or_else(|| {
+                    tracing::warn!(
+                        "session wrapper {:?} is not an absolute path, ignoring it",
+                        session_wrapper
+                    );
+                    Vec::new()
+                }),
+            None => Vec::new(),
+        };
+        Self {
+            x11_launcher_opt,
+            wrapper,
+        }
+    }
+
+    fn command(&self, session_type: SessionType, env: &[String], args: Vec<String>) -> Vec<String> {
+        let mut command = Vec::new();
+        if session_type == SessionType::X11
+            && let Some(x11_launcher) = &self.x11_launcher_opt
+        {
+            command.push(x11_launcher.clone());
+            command.push(crate::x11::ARG.to_string());
+        }
+
+        // The wrapper runs inside the X server, like other clients
+        command.extend(self.wrapper.iter().cloned());
+
+        // Session exec may contain environmental variables
+        command.push("/usr/bin/env".to_string());
+
+        // To ensure the env is set correctly, we also set it in the session command
+        command.extend(env.iter().cloned());
+
+        command.extend(args);
+        command
+    }
+}
+
+fn session_type_env(session_type: SessionType) -> String {
+    match session_type {
+        SessionType::X11 => "XDG_SESSION_TYPE=x11".to_string(),
+        SessionType::Wayland => "XDG_SESSION_TYPE=wayland".to_string(),
+    }
+}
+
+/// Custom sessions of the config that can be started, their ids are added to `seen`
+fn custom_sessions(
+    config: &Config,
+    launcher: &Launcher,
+    seen: &mut HashSet<(String, SessionType)>,
+) -> Vec<Session> {
+    let mut sessions = Vec::new();
+    for custom in config.custom_sessions.iter() {
+        if custom.session_type == SessionType::X11 && launcher.x11_launcher_opt.is_none() {
+            tracing::warn!(
+                "custom session {} needs X11, which is not installed",
+                custom.id
+            );
+            continue;
+        }
+        if custom.exec.is_empty() {
+            tracing::warn!("custom session {} has no command", custom.id);
+            continue;
+        }
+        if !seen.insert((custom.id.clone(), custom.session_type)) {
+            tracing::warn!("custom session {} is defined more than once", custom.id);
+            continue;
+        }
+
+        let mut env = vec![session_type_env(custom.session_type)];
+        env.extend(custom.env.iter().cloned());
+        let command = launcher.command(custom.session_type, &env, custom.exec.clone());
+        tracing::info!(
+            "custom session {} ({}) using command {:?} env {:?}",
+            custom.id,
+            custom.name,
+            command,
+            env
+        );
+        sessions.push(Session {
+            id: custom.id.clone(),
+            name: custom.name.clone(),
+            comment_opt: custom.comment.clone(),
+            icon_opt: custom.icon.clone(),
+            session_type: custom.session_type,
+            command,
+            env,
+        });
+    }
+    sessions
+}
+
+/// Loads all sessions that can be started, sorted by name. Custom sessions from the config
+/// replace desktop files with the same id and session type.
+pub fn load(config: &Config) -> Vec<Session> {
+    // X11 sessions are started by this executable, see [`crate::x11`]
+    let x11_launcher_opt = if x11_available() {
+        match env::current_exe() {
+            Ok(path) => Some(path.to_string_lossy().into_owned()),
+            Err(err) => {
+                tracing::warn!("failed to find X11 session launcher: {:?}", err);
+                None
+            }
+        }
+    } else {
+        tracing::info!("xinit or X server not installed, not loading X11 sessions");
+        None
+    };
+
+    let launcher = Launcher::new(config, x11_launcher_opt);
+
+    let mut seen = HashSet::new();
+    let mut sessions = custom_sessions(config, &launcher, &mut seen);
+
+    let mut session_dirs = xdg::BaseDirectories::with_prefix("wayland-sessions")
+        .get_data_dirs()
+        .into_iter()
+        .map(|dir| (dir, SessionType::Wayland))
+        .collect::<Vec<_>>();
+    if launcher.x11_launcher_opt.is_some() {
+        session_dirs.extend(
+            xdg::BaseDirectories::with_prefix("xsessions")
+                .get_data_dirs()
+                .into_iter()
+                .map(|dir| (dir, SessionType::X11)),
+        );
+    }
+
+    // Data directories are in order of precedence, the first file with an id wins even if it
+    // is hidden, so that users and administrators can mask sessions installed by packages
+    let locales = get_languages_from_env();
+    for (session_dir, session_type) in session_dirs {
+        let read_dir = match fs::read_dir(&session_dir) {
+            Ok(ok) => ok,
+            Err(err) => {
+                tracing::warn!(
+                    "failed to read session directory {:?}: {:?}",
+                    session_dir,
+                    err
+                );
+                continue;
+            }
+        };
+
+        let mut paths = Vec::new();
+        for dir_entry_res in read_dir {
+            match dir_entry_res {
+                Ok(dir_entry) => paths.push(dir_entry.path()),
+                Err(err) => {
+                    tracing::warn!(
+                        "failed to read session directory {:?} entry: {:?}",
+                        session_dir,
+                        err
+                    );
+                }
+            }
+        }
+        // Directory order is arbitrary, keep the log and duplicate handling stable
+        paths.sort();
+
+        for path in paths {
+            if path.extension().is_none_or(|ext| ext != "desktop") {
+                continue;
+            }
+
+            let Some(id) = path.file_stem().and_then(|id| id.to_str()) else {
+                tracing::warn!("failed to read session file {:?}: invalid file name", path);
+                continue;
+            };
+            if !seen.insert((id.to_string(), session_type)) {
+                tracing::info!("session {} at {:?} is overridden", id, path);
+                continue;
+            }
+
+            let entry = match DesktopEntry::from_path(&path, Some(&locales)) {
+                Ok(ok) => ok,
+                Err(err) => {
+                    tracing::warn!("failed to read session file {:?}: {:?}", path, err);
+                    continue;
+                }
+            };
+
+            let desktop_entry = |key: &str| {
+                entry
+                    .groups
+                    .desktop_entry()
+                    .and_then(|g| g.entry(key))
+                    .map(|value| value.to_string())
+            };
+            let is_true = |key: &str| desktop_entry(key).is_some_and(|value| value == "true");
+
+            if is_true("Hidden") || is_true("NoDisplay") {
+                tracing::info!("session {} at {:?} is hidden", id, path);
+                continue;
+            }
+
+            if let Some(program) = desktop_entry("TryExec")
+                && !try_exec(&program)
+            {
+                tracing::info!(
+                    "session {} at {:?} is not installed: {:?} not found",
+                    id,
+                    path,
+                    program
+                );
+                continue;
+            }
+
+            let name = match entry.name(&locales) {
+                Some(some) => some.to_string(),
+                None => {
+                    tracing::warn!(
+                        "failed to read session file {:?}: no Desktop Entry/Name attribute",
+                        path
+                    );
+                    continue;
+                }
+            };
+
+            let exec = match entry.exec() {
+                Some(some) => some,
+                None => {
+                    tracing::warn!(
+                        "failed to read session file {:?}: no Desktop Entry/Exec attribute",
+                        path
+                    );
+                    continue;
+                }
+            };
+
+            let mut env = vec![session_type_env(session_type)];
+
+            if let Some(desktop_names) = desktop_entry("DesktopNames") {
+                env.push(format!("XDG_CURRENT_DESKTOP={desktop_names}"));
+                if let Some(name) = desktop_names.split(':').next() {
+                    env.push(format!("XDG_SESSION_DESKTOP={name}"));
+                }
+            }
+
+            let command = match shlex::split(exec) {
+                Some(args) => launcher.command(session_type, &env, args),
+                None => {
+                    tracing::warn!(
+                        "failed to parse session file {:?} Exec field {:?}",
+                        path,
+                        exec
+                    );
+                    continue;
+                }
+            };
+
+            tracing::info!(
+                "session {} ({}) using command {:?} env {:?}",
+                id,
+                name,
+                command,
+                env
+            );
+            sessions.push(Session {
+                id: id.to_string(),
+                name,
+                comment_opt: entry.comment(&locales).map(|comment| comment.to_string()),
+                icon_opt: entry.icon().map(|icon| icon.to_string()),
+                session_type,
+                command,
+                env,
+            });
+        }
+    }
+
+    sessions.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+    sessions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmic_greeter_config::session::CustomSession;
+
+    const LAUNCHER: &str = "/usr/bin/cosmic-greeter";
+
+    fn custom(id: &str, session_type: SessionType, exec: &[&str]) -> CustomSession {
+        CustomSession {
+            id: id.to_string(),
+            name: id.to_string(),
+            comment: None,
+            icon: None,
+            exec: exec.iter().map(|arg| arg.to_string()).collect(),
+            env: vec!["MOZ_ENABLE_WAYLAND=1".to_string()],
+            session_type,
+        }
+    }
+
+    fn config(session_wrapper: Option<&str>, custom_sessions: Vec<CustomSession>) -> Config {
+        Config {
+            session_wrapper: session_wrapper.map(str::to_string),
+            custom_sessions,
+            ..Default::default()
+        }
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn x11_label() {
+        crate::localize::localizer();
+
+        let session = |session_type| Session {
+            id: "gnome".to_string(),
+            name: "GNOME".to_string(),
+            comment_opt: None,
+            icon_opt: None,
+            session_type,
+            command: Vec::new(),
+            env: Vec::new(),
+        };
+        let wayland = session(SessionType::Wayland).label();
+        let x11 = session(SessionType::X11).label();
+        assert_eq!(wayland, "GNOME");
+        assert_ne!(x11, wayland);
+        assert!(x11.contains("GNOME") && x11.contains("X11"), "{x11:?}");
+    }
+
+    #[test]
+    fn wayland_command() {
+        let launcher = Launcher::new(
+            &config(Some("/etc/cosmic-greeter/wrapper --login"), Vec::new()),
+            Some(LAUNCHER.to_string()),
+        );
+        assert_eq!(
+            launcher.command(
+                SessionType::Wayland,
+                &strings(&["XDG_SESSION_TYPE=wayland"]),
+                strings(&["start-cosmic", "--debug"]),
+            ),
+            strings(&[
+                "/etc/cosmic-greeter/wrapper",
+                "--login",
+                "/usr/bin/env",
+                "XDG_SESSION_TYPE=wayland",
+                "start-cosmic",
+                "--debug",
+            ])
+        );
+    }
+
+    #[test]
+    fn x11_command() {
+        // The wrapper runs inside the X server
+        let launcher = Launcher::new(
+            &config(Some("/etc/cosmic-greeter/wrapper"), Vec::new()),
+            Some(LAUNCHER.to_string()),
+        );
+        assert_eq!(
+            launcher.command(
+                SessionType::X11,
+                &strings(&["XDG_SESSION_TYPE=x11"]),
+                strings(&["/usr/bin/startxfce4"]),
+            ),
+            strings(&[
+                LAUNCHER,
+                crate::x11::ARG,
+                "/etc/cosmic-greeter/wrapper",
+                "/usr/bin/env",
+                "XDG_SESSION_TYPE=x11",
+                "/usr/bin/startxfce4",
+            ])
+        );
+    }
+
+    #[test]
+    fn command_without_wrapper() {
+        let launcher = Launcher::new(&config(None, Vec::new()), None);
+        assert_eq!(
+            launcher.command(
+                SessionType::Wayland,
+                &strings(&["XDG_SESSION_TYPE=wayland", "XDG_CURRENT_DESKTOP=COSMIC"]),
+                strings(&["start-cosmic"]),
+            ),
+            strings(&[
+                "/usr/bin/env",
+                "XDG_SESSION_TYPE=wayland",
+                "XDG_CURRENT_DESKTOP=COSMIC",
+                "start-cosmic",
+            ])
+        );
+    }
+
+    #[test]
+    fn relative_wrapper_is_ignored() {
+        for session_wrapper in ["wrapper --login", "", "\"unterminated"] {
+            let launcher = Launcher::new(&config(Some(session_wrapper), Vec::new()), None);
+            assert!(launcher.wrapper.is_empty(), "{session_wrapper:?}");
+        }
+    }
+
+    #[test]
+    fn custom_session_command() {
+        let config = config(
+            None,
+            vec![custom(
+                "sway",
+                SessionType::Wayland,
+                &["sway", "--unsupported-gpu"],
+            )],
+        );
+        let launcher = Launcher::new(&config, None);
+        let mut seen = HashSet::new();
+        let sessions = custom_sessions(&config, &launcher, &mut seen);
+        assert_eq!(
+            sessions,
+            [Session {
+                id: "sway".to_string(),
+                name: "sway".to_string(),
+                comment_opt: None,
+                icon_opt: None,
+                session_type: SessionType::Wayland,
+                command: strings(&[
+                    "/usr/bin/env",
+                    "XDG_SESSION_TYPE=wayland",
+                    "MOZ_ENABLE_WAYLAND=1",
+                    "sway",
+                    "--unsupported-gpu",
+                ]),
+                env: strings(&["XDG_SESSION_TYPE=wayland", "MOZ_ENABLE_WAYLAND=1"]),
+            }]
+        );
+        // Desktop files with the same id and session type are overridden
+        assert!(seen.contains(&("sway".to_string(), SessionType::Wayland)));
+    }
+
+    #[test]
+    fn custom_sessions_rejected() {
+        let config = config(
+            None,
+            vec![
+                custom("xfce", SessionType::X11, &["/usr/bin/startxfce4"]),
+                custom("empty", SessionType::Wayland, &[]),
+                custom("sway", SessionType::Wayland, &["sway"]),
+                custom("sway", SessionType::Wayland, &["sway", "--debug"]),
+            ],
+        );
+
+        // X11 sessions need xinit, the first session with an id wins
+        let launcher = Launcher::new(&config, None);
+        let sessions = custom_sessions(&config, &launcher, &mut HashSet::new());
+        assert_eq!(
+            sessions
+                .iter()
+                .map(|session| (
+                    session.id.as_str(),
+                    session.command.last().unwrap().as_str()
+                ))
+                .collect::<Vec<_>>(),
+            [("sway", "sway")]
+        );
+
+        let launcher = Launcher::new(&config, Some(LAUNCHER.to_string()));
+        let sessions = custom_sessions(&config, &launcher, &mut HashSet::new());
+        assert_eq!(
+            sessions
+                .iter()
+                .map(|session| session.id.as_str())
+                .collect::<Vec<_>>(),
+            ["xfce", "sway"]
+        );
+    }
+}
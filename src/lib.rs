@@ -22,3 +22,5 @@ mod networkmanager;
 mod upower;
 
 mod time;
+
+pub mod x11;
@@ -5,7 +5,7 @@ use color_eyre::eyre::WrapErr;
 use cosmic::app::{Core, Settings, Task};
 use cosmic::cctk::wayland_protocols::xdg::shell::client::xdg_positioner::Gravity;
 use cosmic::iced::event::wayland::{OutputEvent, SessionLockEvent};
-use cosmic::iced::futures::{self, SinkExt};
+use cosmic::iced::futures::{self, SinkExt, StreamExt};
 use cosmic::iced::platform_specific::shell::wayland::commands::session_lock::{
     destroy_lock_surface, get_lock_surface, lock, unlock,
 };
@@ -16,13 +16,15 @@ use cosmic::iced::{
 };
 use cosmic::{Element, executor, surface, theme, widget};
 use cosmic_config::CosmicConfigEntry;
-use cosmic_greeter_daemon::{TimeAppletConfig, UserData};
+use cosmic_greeter_config::Config as CosmicGreeterConfig;
+use cosmic_greeter_daemon::{AccountInfo, TimeAppletConfig, UserData};
 use std::any::TypeId;
+use std::collections::{HashMap, HashSet};
 use std::ffi::{CStr, CString};
 use std::os::fd::OwnedFd;
 use std::path::PathBuf;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{Arc, PoisonError};
+use std::time::{Duration, Instant};
 use std::{env, fs, process};
 use tokio::sync::mpsc;
 use tokio::task;
@@ -36,6 +38,12 @@ use wayland_client::protocol::wl_output::WlOutput;
 use crate::common::{self, Common, DEFAULT_MENU_ITEM_HEIGHT};
 use crate::fl;
 
+mod biometric;
+mod slideshow;
+
+use biometric::{Biometric, BiometricKind, BiometricStatus};
+use slideshow::Slideshow;
+
 fn lockfile_opt() -> Option<PathBuf> {
     let runtime_dir = dirs::runtime_dir()?;
     let session_id = env::var("XDG_SESSION_ID").ok()?;
@@ -76,12 +84,26 @@ pub fn main(user: pwd::Passwd) -> Result<(), Box<dyn std::error::Error>> {
     crate::localize::localize();
 
     let mut user_data = UserData::from(user);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    match runtime.block_on(async {
+        let connection = zbus::Connection::system().await?;
+        AccountInfo::load(&connection, user_data.uid).await
+    }) {
+        Ok(account) => user_data.set_account(account),
+        Err(err) => {
+            tracing::warn!("failed to load account info: {}", err);
+        }
+    }
     // We are already the user at this point
     user_data.load_config_as_user();
+    user_data.load_wallpapers_as_user();
 
     let logind_available = cfg!(feature = "logind") && crate::logind::is_available();
 
     let flags = Flags {
+        greeter_config: CosmicGreeterConfig::load_system().0,
         user_icon: user_data
             .icon_opt
             .take()
@@ -98,36 +120,52 @@ pub fn main(user: pwd::Passwd) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-/// Convert PAM errors to user-friendly localized messages
-fn pam_error_to_message(error: &pam_client::Error) -> String {
-    use pam_client::ErrorCode;
-
-    // Use the structured error code instead of string matching for reliability
-    match error.code() {
-        ErrorCode::AUTH_ERR | ErrorCode::CRED_INSUFFICIENT => {
-            fl!("auth-error-credentials")
+fn biometric_indicator<'a>(biometric: &Biometric) -> Element<'a, Message> {
+    let (icon, text) = match (biometric.kind, biometric.status) {
+        (BiometricKind::Fingerprint, BiometricStatus::Waiting) => {
+            ("fingerprint-symbolic", fl!("fingerprint-waiting"))
+        }
+        (BiometricKind::Fingerprint, BiometricStatus::Matched) => {
+            ("emblem-ok-symbolic", fl!("fingerprint-matched"))
         }
-        ErrorCode::PERM_DENIED => {
-            fl!("auth-error-denied")
+        (BiometricKind::Fingerprint, BiometricStatus::Failed) => {
+            ("dialog-error-symbolic", fl!("fingerprint-failed"))
         }
-        ErrorCode::MAXTRIES => {
-            fl!("auth-error-maxtries")
+        (BiometricKind::SecurityKey, BiometricStatus::Waiting) => {
+            ("security-high-symbolic", fl!("security-key-waiting"))
         }
-        ErrorCode::ACCT_EXPIRED | ErrorCode::USER_UNKNOWN => {
-            fl!("auth-error-account")
+        (BiometricKind::SecurityKey, BiometricStatus::Matched) => {
+            ("emblem-ok-symbolic", fl!("security-key-matched"))
         }
-        _ => {
-            // For any other error, show a generic message
-            fl!("auth-error-default")
+        (BiometricKind::SecurityKey, BiometricStatus::Failed) => {
+            ("dialog-error-symbolic", fl!("security-key-failed"))
         }
+    };
+
+    let mut row = widget::row::with_capacity(3)
+        .spacing(8.0)
+        .align_y(Alignment::Center)
+        .push(widget::icon::from_name(icon).size(16))
+        .push(widget::text(text));
+    if let Some(retries) = biometric.retries_opt {
+        row = row.push(widget::text(fl!("biometric-retries", retries = retries)));
     }
+
+    widget::container(row)
+        .width(Length::Fill)
+        .align_x(Alignment::Center)
+        .into()
 }
 
-pub fn pam_thread(username: String, conversation: Conversation) -> Result<(), pam_client::Error> {
+pub fn pam_thread(
+    service: &str,
+    username: String,
+    conversation: Conversation,
+) -> Result<(), pam_client::Error> {
     //TODO: send errors to GUI, restart process
 
     // Create PAM context
-    let mut context = pam_client::Context::new("cosmic-greeter", Some(&username), conversation)?;
+    let mut context = pam_client::Context::new(service, Some(&username), conversation)?;
 
     // Authenticate the user (ask for password, 2nd-factor token, fingerprint, etc.)
     tracing::info!("authenticate");
@@ -135,17 +173,64 @@ pub fn pam_thread(username: String, conversation: Conversation) -> Result<(), pa
 
     // Validate the account (is not locked, expired, etc.)
     tracing::info!("acct_mgmt");
-    context.acct_mgmt(pam_client::Flag::NONE)?;
+    match context.acct_mgmt(pam_client::Flag::NONE) {
+        Ok(()) => {}
+        Err(err)
+            if err.code() == pam_client::ErrorCode::NEW_AUTHTOK_REQD
+                && context.conversation().value_rx_opt.is_some() =>
+        {
+            // The password expired, change it in the same conversation
+            context.conversation_mut().password_change();
+            tracing::info!("chauthtok");
+            context.chauthtok(pam_client::Flag::CHANGE_EXPIRED_AUTHTOK)?;
+        }
+        Err(err) => return Err(err),
+    }
 
     Ok(())
 }
 
 pub struct Conversation {
     msg_tx: futures::channel::mpsc::Sender<cosmic::Action<Message>>,
-    value_rx: mpsc::Receiver<String>,
+    /// Values typed by the user, `None` for the conversation running next to the password one
+    value_rx_opt: Option<mpsc::Receiver<String>>,
 }
 
 impl Conversation {
+    fn password_change(&mut self) {
+        let res = futures::executor::block_on(async {
+            self.msg_tx
+                .send(cosmic::Action::App(common::Message::PasswordChange.into()))
+                .await
+        });
+        if let Err(err) = res {
+            tracing::warn!("failed to send password change: {:?}", err);
+        }
+    }
+
+    /// Shows fingerprint and security key messages in the indicator instead of the prompt
+    fn biometric(&mut self, prompt: &str) -> Result<bool, pam_client::ErrorCode> {
+        if self.msg_tx.is_closed() {
+            // The conversation was cancelled
+            return Err(pam_client::ErrorCode::CONV_ERR);
+        }
+        let Some(biometric) = Biometric::from_message(prompt) else {
+            return Ok(false);
+        };
+
+        futures::executor::block_on(async {
+            self.msg_tx
+                .send(cosmic::Action::App(Message::Biometric(Some(biometric))))
+                .await
+        })
+        .map_err(|err| {
+            tracing::error!("failed to send biometric state: {:?}", err);
+            pam_client::ErrorCode::CONV_ERR
+        })?;
+
+        Ok(true)
+    }
+
     fn prompt_value(
         &mut self,
         prompt_c: &CStr,
@@ -156,10 +241,30 @@ impl Conversation {
             pam_client::ErrorCode::CONV_ERR
         })?;
 
+        let Some(value_rx) = &mut self.value_rx_opt else {
+            // Security keys may ask to press enter once they are inserted
+            if !secret && self.biometric(prompt)? {
+                return Ok(CString::default());
+            }
+            tracing::warn!(
+                "prompt {:?} can not be answered without the password field",
+                prompt
+            );
+            return Err(pam_client::ErrorCode::CONV_ERR);
+        };
+
         futures::executor::block_on(async {
             self.msg_tx
                 .send(cosmic::Action::App(
-                    common::Message::Prompt(prompt.to_string(), secret, Some(String::new())).into(),
+                    common::Message::AuthPrompt(
+                        if secret {
+                            common::PromptKind::Secret
+                        } else {
+                            common::PromptKind::Visible
+                        },
+                        prompt.to_string(),
+                    )
+                    .into(),
                 ))
                 .await
         })
@@ -168,7 +273,7 @@ impl Conversation {
             pam_client::ErrorCode::CONV_ERR
         })?;
 
-        let value = self.value_rx.blocking_recv().ok_or_else(|| {
+        let value = value_rx.blocking_recv().ok_or_else(|| {
             tracing::error!("failed to receive value: channel closed");
             pam_client::ErrorCode::CONV_ERR
         })?;
@@ -185,10 +290,16 @@ impl Conversation {
             pam_client::ErrorCode::CONV_ERR
         })?;
 
+        // Keep the password prompt while waiting for a fingerprint
+        if self.biometric(prompt)? || self.value_rx_opt.is_none() {
+            return Ok(());
+        }
+
         futures::executor::block_on(async {
             self.msg_tx
                 .send(cosmic::Action::App(
-                    common::Message::Prompt(prompt.to_string(), false, None).into(),
+                    common::Message::AuthPrompt(common::PromptKind::Info, prompt.to_string())
+                        .into(),
                 ))
                 .await
         })
@@ -204,9 +315,17 @@ impl Conversation {
             pam_client::ErrorCode::CONV_ERR
         })?;
 
+        // Errors of the other conversation would hide the state of the password prompt
+        if self.biometric(prompt)? || self.value_rx_opt.is_none() {
+            return Ok(());
+        }
+
         futures::executor::block_on(async {
             self.msg_tx
-                .send(cosmic::Action::App(Message::Error(prompt.to_string())))
+                .send(cosmic::Action::App(
+                    common::Message::AuthPrompt(common::PromptKind::Error, prompt.to_string())
+                        .into(),
+                ))
                 .await
         })
         .map_err(|err| {
@@ -247,6 +366,7 @@ impl pam_client::ConversationHandler for Conversation {
 
 #[derive(Clone)]
 pub struct Flags {
+    greeter_config: CosmicGreeterConfig,
     user_data: UserData,
     user_icon: Option<widget::image::Handle>,
     lockfile_opt: Option<PathBuf>,
@@ -267,7 +387,12 @@ pub enum Message {
     OutputEvent(OutputEvent, WlOutput),
     SessionLockEvent(SessionLockEvent),
     Channel(mpsc::Sender<String>),
+    Biometric(Option<Biometric>),
+    BiometricMatched,
     BackgroundState(cosmic_bg_config::state::State),
+    SlideshowNext(String),
+    SlideshowImage(String, Option<widget::image::Handle>),
+    SlideshowFrame(Instant),
     DropdownToggle(Dropdown),
     KeyboardLayout(usize),
     Inhibit(Arc<OwnedFd>),
@@ -314,10 +439,48 @@ pub struct App {
     dropdown_opt: Option<Dropdown>,
     inhibit_opt: Option<Arc<OwnedFd>>,
     value_tx_opt: Option<mpsc::Sender<String>>,
+    /// Value submitted while the previous one was still being sent
+    pending_value_opt: Option<String>,
     authenticating: bool,
+    /// Fingerprint or security key indicator
+    biometric_opt: Option<Biometric>,
+    /// Slideshows by the output name of their cosmic-bg config entry
+    slideshows: HashMap<String, Slideshow>,
+    /// Backgrounds that are fading out, with the time the fade started
+    fades: HashMap<SurfaceId, (common::Background, Instant)>,
 }
 
 impl App {
+    /// Creates slideshows for outputs with a directory background, keeping unchanged ones.
+    /// Returns the config entry names of the slideshows that were kept.
+    fn update_slideshows(&mut self) -> HashSet<String> {
+        let user_data = &self.flags.user_data;
+        let mut kept = HashSet::new();
+        let mut slideshows = HashMap::new();
+        for output_name in self.common.output_names.values() {
+            let Some(entry) = user_data.bg_entry(output_name) else {
+                continue;
+            };
+            if slideshows.contains_key(&entry.output) {
+                continue;
+            }
+            let Some(slideshow) = Slideshow::new(entry, user_data.bg_source(output_name)) else {
+                continue;
+            };
+            match self.slideshows.remove(&entry.output) {
+                Some(old) if old.dir == slideshow.dir && old.rotation == slideshow.rotation => {
+                    kept.insert(entry.output.clone());
+                    slideshows.insert(entry.output.clone(), old);
+                }
+                _ => {
+                    slideshows.insert(entry.output.clone(), slideshow);
+                }
+            }
+        }
+        self.slideshows = slideshows;
+        kept
+    }
+
     fn menu(&self, surface_id: SurfaceId) -> Element<'_, Message> {
         let window_width = self
             .common
@@ -514,6 +677,9 @@ impl App {
                     .align_x(Alignment::Center),
             );
 
+            if let Some(history) = self.common.prompt_history() {
+                column = column.push(history);
+            }
             if let Some((prompt, secret, value_opt)) = &self.common.prompt_opt {
                 match value_opt {
                     Some(value) => {
@@ -525,23 +691,31 @@ impl App {
                             .cloned()
                             .unwrap_or_else(|| cosmic::widget::Id::new("text_input"));
 
-                        let mut text_input = widget::secure_input(
-                            prompt.clone(),
-                            value.as_str(),
-                            Some(
-                                common::Message::Prompt(
-                                    prompt.clone(),
-                                    !*secret,
-                                    Some(value.clone()),
+                        let mut text_input =
+                            if self.common.prompt_kind == common::PromptKind::Visible {
+                                widget::text_input(
+                                    self.common.prompt_placeholder(prompt),
+                                    value.as_str(),
+                                )
+                            } else {
+                                widget::secure_input(
+                                    self.common.prompt_placeholder(prompt),
+                                    value.as_str(),
+                                    Some(
+                                        common::Message::Prompt(
+                                            prompt.clone(),
+                                            !*secret,
+                                            Some(value.clone()),
+                                        )
+                                        .into(),
+                                    ),
+                                    *secret,
                                 )
-                                .into(),
-                            ),
-                            *secret,
-                        )
-                        .id(text_input_id);
-
-                        // Don't allow input when authenticating
-                        if !self.authenticating {
+                            }
+                            .id(text_input_id);
+
+                        // Don't allow input when authenticating or locked out
+                        if !self.authenticating && self.common.lockout_remaining().is_none() {
                             text_input = text_input
                                 .on_input(|input| {
                                     common::Message::Prompt(prompt.clone(), *secret, Some(input))
@@ -568,6 +742,14 @@ impl App {
                 }
             }
 
+            if let Some(biometric) = &self.biometric_opt {
+                column = column.push(biometric_indicator(biometric));
+            }
+
+            if let Some(attempts) = self.common.attempts() {
+                column = column.push(attempts);
+            }
+
             // Show either authenticating message or error message in the same location
             if self.authenticating {
                 column = column.push(
@@ -667,7 +849,10 @@ impl cosmic::Application for App {
             Message::OutputEvent(output_event, output)
         }));
         common.on_session_lock_event = Some(Box::new(Message::SessionLockEvent));
+        common.on_submit = Some(Box::new(Message::Submit));
         common.update_user_data(&flags.user_data);
+        common.wallpaper_blur_radius = flags.greeter_config.wallpaper_blur_radius;
+        common.wallpaper_dim = flags.greeter_config.wallpaper_dim;
 
         let already_locked = match flags.lockfile_opt {
             Some(ref lockfile) => lockfile.exists(),
@@ -681,7 +866,11 @@ impl cosmic::Application for App {
             dropdown_opt: None,
             inhibit_opt: None,
             value_tx_opt: None,
+            pending_value_opt: None,
             authenticating: false,
+            biometric_opt: None,
+            slideshows: HashMap::new(),
+            fades: HashMap::new(),
         };
 
         let task = if cfg!(feature = "logind") && app.flags.logind_available {
@@ -709,13 +898,30 @@ impl cosmic::Application for App {
         match message {
             Message::None => {}
             Message::Common(common_message) => {
-                return self.common.update(common_message);
+                if let common::Message::AuthPrompt(kind, _) = &common_message
+                    && kind.is_input()
+                {
+                    // The previous answer was accepted, allow input again
+                    self.authenticating = false;
+                }
+                let path_image = matches!(common_message, common::Message::PathImage(..));
+                let task = self.common.update(common_message);
+                if path_image {
+                    // Show the wallpaper image that finished loading
+                    return Task::batch([
+                        task,
+                        self.common.update_wallpapers(&self.flags.user_data),
+                    ]);
+                }
+                return task;
             }
             Message::OutputEvent(output_event, output) => {
                 match output_event {
                     OutputEvent::Created(output_info_opt) => {
                         tracing::info!("output {}: created", output.id());
 
+                        let mut tasks = Vec::new();
+
                         let surface_id = SurfaceId::unique();
                         let subsurface_id = SurfaceId::unique();
 
@@ -754,8 +960,10 @@ impl cosmic::Application for App {
                                     self.common
                                         .surface_names
                                         .insert(subsurface_id, output_name.clone());
-                                    self.common.surface_images.remove(&surface_id);
-                                    self.common.update_wallpapers(&self.flags.user_data);
+                                    self.common.surface_backgrounds.remove(&surface_id);
+                                    tasks
+                                        .push(self.common.update_wallpapers(&self.flags.user_data));
+                                    self.update_slideshows();
                                     let text_input_id =
                                         widget::Id::new(format!("input-{output_name}",));
                                     self.common
@@ -812,18 +1020,20 @@ impl cosmic::Application for App {
                         );
 
                         if matches!(self.state, State::Locked { .. }) {
-                            return get_lock_surface(surface_id, output).chain({
+                            tasks.push(get_lock_surface(surface_id, output).chain({
                                 cosmic::task::message(cosmic::Action::Cosmic(
                                     cosmic::app::Action::Surface(msg),
                                 ))
-                            });
+                            }));
                         }
+                        return Task::batch(tasks);
                     }
                     OutputEvent::Removed => {
                         tracing::info!("output {}: removed", output.id());
                         match self.common.surface_ids.remove(&output) {
                             Some(surface_id) => {
-                                self.common.surface_images.remove(&surface_id);
+                                self.common.surface_backgrounds.remove(&surface_id);
+                                self.fades.remove(&surface_id);
                                 self.common.surface_names.remove(&surface_id);
                                 self.common.window_size.remove(&surface_id);
                                 if let Some(n) = self.common.surface_names.remove(&surface_id) {
@@ -896,6 +1106,13 @@ impl cosmic::Application for App {
 
                                 let pam_future = async {
                                     loop {
+                                        msg_tx
+                                            .send(cosmic::Action::App(
+                                                common::Message::PromptReset.into(),
+                                            ))
+                                            .await
+                                            .unwrap();
+
                                         let (value_tx, value_rx) = mpsc::channel(16);
                                         msg_tx
                                             .send(cosmic::Action::App(Message::Channel(value_tx)))
@@ -907,8 +1124,12 @@ impl cosmic::Application for App {
                                             let msg_tx = msg_tx.clone();
                                             task::spawn_blocking(move || {
                                                 pam_thread(
+                                                    "cosmic-greeter",
                                                     username,
-                                                    Conversation { msg_tx, value_rx },
+                                                    Conversation {
+                                                        msg_tx,
+                                                        value_rx_opt: Some(value_rx),
+                                                    },
                                                 )
                                             })
                                             .await
@@ -925,10 +1146,19 @@ impl cosmic::Application for App {
                                                 break;
                                             }
                                             Err(err) => {
-                                                tracing::warn!("authentication error: {}", err);
+                                                let error = common::AuthError::from_pam(err.code());
+                                                error.log(&err);
+                                                if error == common::AuthError::Credentials {
+                                                    msg_tx
+                                                        .send(cosmic::Action::App(
+                                                            common::Message::AuthFailed.into(),
+                                                        ))
+                                                        .await
+                                                        .unwrap();
+                                                }
                                                 msg_tx
                                                     .send(cosmic::Action::App(Message::Error(
-                                                        pam_error_to_message(&err),
+                                                        error.message(),
                                                     )))
                                                     .await
                                                     .unwrap();
@@ -937,9 +1167,112 @@ impl cosmic::Application for App {
                                     }
                                 };
 
+                                // Fingerprints and security keys are checked next to the password
+                                let biometric_future = {
+                                    let mut msg_tx = msg_tx.clone();
+                                    let username = username.clone();
+                                    async move {
+                                        if !biometric::service_available() {
+                                            return futures::future::pending().await;
+                                        }
+                                        let mut failures = 0;
+                                        loop {
+                                            // The conversation has its own channel, which is
+                                            // closed when this future is dropped on unlock. Its
+                                            // next message then fails with CONV_ERR.
+                                            let (conversation_tx, mut conversation_rx) =
+                                                futures::channel::mpsc::channel(16);
+                                            let pam_handle = {
+                                                let username = username.clone();
+                                                task::spawn_blocking(move || {
+                                                    // Wait until a cancelled conversation
+                                                    // releases the reader
+                                                    let _reader = biometric::READER
+                                                        .lock()
+                                                        .unwrap_or_else(PoisonError::into_inner);
+                                                    pam_thread(
+                                                        biometric::SERVICE,
+                                                        username,
+                                                        Conversation {
+                                                            msg_tx: conversation_tx,
+                                                            value_rx_opt: None,
+                                                        },
+                                                    )
+                                                })
+                                            };
+                                            let forward = async {
+                                                while let Some(action) =
+                                                    conversation_rx.next().await
+                                                {
+                                                    let _ = msg_tx.send(action).await;
+                                                }
+                                            };
+                                            let (pam_res, ()) = futures::join!(pam_handle, forward);
+                                            let pam_res = pam_res.unwrap();
+
+                                            match pam_res {
+                                                Ok(()) => {
+                                                    tracing::info!(
+                                                        "successfully authenticated with {}",
+                                                        biometric::SERVICE
+                                                    );
+                                                    let _ = msg_tx
+                                                        .send(cosmic::Action::App(
+                                                            Message::BiometricMatched,
+                                                        ))
+                                                        .await;
+                                                    let _ = msg_tx
+                                                        .send(cosmic::Action::App(Message::Unlock))
+                                                        .await;
+                                                    break;
+                                                }
+                                                Err(err)
+                                                    if err.code()
+                                                        == pam_client::ErrorCode::AUTH_ERR
+                                                        && failures + 1
+                                                            < biometric::MAX_FAILURES =>
+                                                {
+                                                    // Try again after a while, the password can
+                                                    // still be used
+                                                    failures += 1;
+                                                    tracing::info!(
+                                                        "{} authentication error: {}",
+                                                        biometric::SERVICE,
+                                                        err
+                                                    );
+                                                    tokio::time::sleep(
+                                                        biometric::RETRY_DELAY * failures,
+                                                    )
+                                                    .await;
+                                                }
+                                                Err(err) => {
+                                                    // Too many failed attempts, no reader, nothing
+                                                    // enrolled, or a broken service
+                                                    tracing::warn!(
+                                                        "{} unavailable: {}",
+                                                        biometric::SERVICE,
+                                                        err
+                                                    );
+                                                    let _ = msg_tx
+                                                        .send(cosmic::Action::App(
+                                                            Message::Biometric(None),
+                                                        ))
+                                                        .await;
+                                                    return futures::future::pending().await;
+                                                }
+                                            }
+                                        }
+                                    }
+                                };
+
                                 futures::pin_mut!(heartbeat_future);
                                 futures::pin_mut!(pam_future);
-                                futures::future::select(heartbeat_future, pam_future).await;
+                                futures::pin_mut!(biometric_future);
+                                futures::future::select(
+                                    heartbeat_future,
+                                    futures::future::select(pam_future, biometric_future),
+                                )
+                                .await;
                             },
                         ))
                         .abortable();
@@ -1024,12 +1357,80 @@ impl cosmic::Application for App {
             },
             Message::Channel(value_tx) => {
                 self.value_tx_opt = Some(value_tx);
+                if let Some(value) = self.pending_value_opt.take() {
+                    return self.update(Message::Submit(value));
+                }
+            }
+            Message::Biometric(biometric_opt) => {
+                self.biometric_opt = biometric_opt;
+            }
+            Message::BiometricMatched => {
+                if let Some(biometric) = &mut self.biometric_opt {
+                    biometric.status = BiometricStatus::Matched;
+                    biometric.retries_opt = None;
+                }
             }
             Message::BackgroundState(bg_state) => {
                 self.flags.user_data.bg_state = bg_state;
+                // The state changes whenever cosmic-bg applies a changed config
+                self.flags.user_data.load_bg_config_as_user();
                 self.flags.user_data.load_wallpapers_as_user();
-                self.common.surface_images.clear();
-                self.common.update_wallpapers(&self.flags.user_data);
+                // Slideshows are rotated by the locker, so keep showing their current image
+                let kept = self.update_slideshows();
+                let user_data = &self.flags.user_data;
+                let surface_names = &self.common.surface_names;
+                self.common.surface_backgrounds.retain(|surface_id, _| {
+                    surface_names
+                        .get(surface_id)
+                        .and_then(|output_name| user_data.bg_entry(output_name))
+                        .is_some_and(|entry| kept.contains(&entry.output))
+                });
+                return self.common.update_wallpapers(&self.flags.user_data);
+            }
+            Message::SlideshowNext(entry_output) => {
+                if let Some(slideshow) = self.slideshows.get_mut(&entry_output) {
+                    let path = slideshow.next().to_path_buf();
+                    let blur_radius = self.common.wallpaper_blur_radius;
+                    return cosmic::task::future(async move {
+                        let image_opt = match tokio::fs::read(&path).await {
+                            Ok(bytes) => common::wallpaper_image(bytes, blur_radius).await,
+                            Err(err) => {
+                                tracing::error!("failed to read wallpaper {:?}: {:?}", path, err);
+                                None
+                            }
+                        };
+                        Message::SlideshowImage(entry_output, image_opt)
+                    });
+                }
+            }
+            Message::SlideshowImage(entry_output, image_opt) => {
+                let Some(image) = image_opt else {
+                    return Task::none();
+                };
+                for surface_id in self.common.surface_ids.values() {
+                    let Some(output_name) = self.common.surface_names.get(surface_id) else {
+                        continue;
+                    };
+                    let Some(entry) = self.flags.user_data.bg_entry(output_name) else {
+                        continue;
+                    };
+                    if entry.output != entry_output {
+                        continue;
+                    }
+                    let background =
+                        common::Background::Image(image.clone(), entry.scaling_mode.clone());
+                    if let Some(previous) = self
+                        .common
+                        .surface_backgrounds
+                        .insert(*surface_id, background)
+                    {
+                        self.fades.insert(*surface_id, (previous, Instant::now()));
+                    }
+                }
+            }
+            Message::SlideshowFrame(now) => {
+                self.fades
+                    .retain(|_, (_, start)| now.duration_since(*start) < slideshow::FADE);
             }
             Message::DropdownToggle(dropdown) => {
                 if self.dropdown_opt == Some(dropdown) {
@@ -1062,7 +1463,13 @@ impl cosmic::Application for App {
                 if value.is_empty() {
                     return Task::none();
                 }
+                if self.common.lockout_remaining().is_some() {
+                    return Task::none();
+                }
                 self.common.error_opt = None;
+                if !self.common.check_submit(&value) {
+                    return Task::none();
+                }
                 self.authenticating = true;
                 match self.value_tx_opt.take() {
                     Some(value_tx) => {
@@ -1071,7 +1478,11 @@ impl cosmic::Application for App {
                             Message::Channel(value_tx)
                         });
                     }
-                    None => tracing::warn!("tried to submit when value_tx_opt not set"),
+                    None => {
+                        // Answers to PAM may be submitted before the channel is returned
+                        tracing::debug!("value_tx_opt not set, sending value when it is");
+                        self.pending_value_opt = Some(value);
+                    }
                 }
             }
             Message::Suspend => {
@@ -1097,8 +1508,11 @@ impl cosmic::Application for App {
                     self.common.error_opt = None;
                     // Clear value_tx
                     self.value_tx_opt = None;
+                    self.pending_value_opt = None;
                     // Reset authenticating state
                     self.authenticating = false;
+                    // Clear fingerprint state
+                    self.biometric_opt = None;
                     // Try to create lockfile when locking
                     if let Some(ref lockfile) = self.flags.lockfile_opt
                         && let Err(err) = fs::File::create(lockfile)
@@ -1124,8 +1538,14 @@ impl cosmic::Application for App {
                         self.common.error_opt = None;
                         // Clear value_tx
                         self.value_tx_opt = None;
+                        self.pending_value_opt = None;
                         // Stop authenticating
                         self.authenticating = false;
+                        // Start counting again on the next lock
+                        self.common.failed_attempts = 0;
+                        self.common.lockout_until_opt = None;
+                        // Clear fingerprint state
+                        self.biometric_opt = None;
 
                         // Try to delete lockfile when unlocking
                         if let Some(ref lockfile) = self.flags.lockfile_opt
@@ -1173,16 +1593,16 @@ impl cosmic::Application for App {
 
     /// Creates a view after each update.
     fn view_window(&self, surface_id: SurfaceId) -> Element<'_, Self::Message> {
-        let img = self
-            .common
-            .surface_images
-            .get(&surface_id)
-            .unwrap_or(&self.common.fallback_background);
-        widget::image(img)
-            .content_fit(iced::ContentFit::Cover)
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .into()
+        if let Some((previous, start)) = self.fades.get(&surface_id)
+            && let Some(current) = self.common.surface_backgrounds.get(&surface_id)
+        {
+            // Fade in the next image of a slideshow
+            let opacity = start.elapsed().as_secs_f32() / slideshow::FADE.as_secs_f32();
+            return self.common.dimmed(
+                iced::widget::stack![previous.view(1.0), current.view(opacity.min(1.0))].into(),
+            );
+        }
+        self.common.background(surface_id)
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
@@ -1205,6 +1625,17 @@ impl cosmic::Application for App {
             }),
         );
 
+        for (entry_output, slideshow) in self.slideshows.iter() {
+            subscriptions.push(
+                slideshow
+                    .subscription(entry_output)
+                    .map(Message::SlideshowNext),
+            );
+        }
+        if !self.fades.is_empty() {
+            subscriptions.push(iced::window::frames().map(Message::SlideshowFrame));
+        }
+
         struct TimeAppletSubscription;
         subscriptions.push(
             cosmic_config::config_subscription(
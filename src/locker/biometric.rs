@@ -0,0 +1,158 @@
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// PAM service used for the conversation running next to the password one
+pub const SERVICE: &str = "cosmic-greeter-fingerprint";
+
+/// Held by the conversation while it runs. Only one conversation can use the reader, a new
+/// one waits until a cancelled one returns.
+pub static READER: Mutex<()> = Mutex::new(());
+
+/// Failed conversations in a row after which only the password is accepted
+pub const MAX_FAILURES: u32 = 5;
+
+/// Delay before the next conversation after a failed one, multiplied by the failures so far
+pub const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The parallel conversation is only started when the administrator configured its service
+pub fn service_available() -> bool {
+    Path::new("/etc/pam.d").join(SERVICE).is_file()
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BiometricKind {
+    Fingerprint,
+    SecurityKey,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BiometricStatus {
+    Waiting,
+    Matched,
+    Failed,
+}
+
+/// State of the fingerprint or security key indicator
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Biometric {
+    pub kind: BiometricKind,
+    pub status: BiometricStatus,
+    pub retries_opt: Option<u32>,
+}
+
+impl Biometric {
+    /// Recognises messages sent by pam_fprintd and pam_u2f, returns `None` for anything else
+    pub fn from_message(message: &str) -> Option<Self> {
+        // Only English messages are recognised, see `is_password_expired_message` in common.rs
+        let lower = message.to_lowercase();
+        let kind = if ["finger", "swipe", "fprint"]
+            .iter()
+            .any(|word| lower.contains(word))
+        {
+            BiometricKind::Fingerprint
+        } else if [
+            "security key",
+            "u2f",
+            "fido",
+            "touch the device",
+            "touch your device",
+        ]
+        .iter()
+        .any(|word| lower.contains(word))
+        {
+            BiometricKind::SecurityKey
+        } else {
+            return None;
+        };
+
+        let status = if ["failed", "not match", "no match", "not recognized", "again"]
+            .iter()
+            .any(|word| lower.contains(word))
+        {
+            BiometricStatus::Failed
+        } else {
+            BiometricStatus::Waiting
+        };
+
+        // Messages like "2 tries left" or "attempts remaining: 2"
+        let retries_opt = if lower.contains("left") || lower.contains("remaining") {
+            lower
+                .split(|c: char| !c.is_ascii_digit())
+                .find(|word| !word.is_empty())
+                .and_then(|word| word.parse().ok())
+        } else {
+            None
+        };
+
+        Some(Self {
+            kind,
+            status,
+            retries_opt,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn biometric(
+        kind: BiometricKind,
+        status: BiometricStatus,
+        retries_opt: Option<u32>,
+    ) -> Option<Biometric> {
+        Some(Biometric {
+            kind,
+            status,
+            retries_opt,
+        })
+    }
+
+    #[test]
+    fn fingerprint_messages() {
+        assert_eq!(
+            Biometric::from_message("Place your right index finger on the fingerprint reader"),
+            biometric(BiometricKind::Fingerprint, BiometricStatus::Waiting, None)
+        );
+        assert_eq!(
+            Biometric::from_message("Swipe your finger across the reader"),
+            biometric(BiometricKind::Fingerprint, BiometricStatus::Waiting, None)
+        );
+        assert_eq!(
+            Biometric::from_message("Failed to match fingerprint"),
+            biometric(BiometricKind::Fingerprint, BiometricStatus::Failed, None)
+        );
+        assert_eq!(
+            Biometric::from_message("Fingerprint not recognized, 2 tries left"),
+            biometric(BiometricKind::Fingerprint, BiometricStatus::Failed, Some(2))
+        );
+        assert_eq!(
+            Biometric::from_message("Finger not matched, attempts remaining: 1"),
+            biometric(BiometricKind::Fingerprint, BiometricStatus::Failed, Some(1))
+        );
+    }
+
+    #[test]
+    fn security_key_messages() {
+        assert_eq!(
+            Biometric::from_message("Please touch the device."),
+            biometric(BiometricKind::SecurityKey, BiometricStatus::Waiting, None)
+        );
+        assert_eq!(
+            Biometric::from_message("Insert your U2F device, then press ENTER."),
+            biometric(BiometricKind::SecurityKey, BiometricStatus::Waiting, None)
+        );
+        assert_eq!(
+            Biometric::from_message("Security key verification failed"),
+            biometric(BiometricKind::SecurityKey, BiometricStatus::Failed, None)
+        );
+    }
+
+    #[test]
+    fn other_messages() {
+        assert_eq!(Biometric::from_message("Password: "), None);
+        assert_eq!(Biometric::from_message("Verification code:"), None);
+        assert_eq!(Biometric::from_message(""), None);
+    }
+}
@@ -0,0 +1,277 @@
+use cosmic::iced::futures::SinkExt;
+use cosmic::iced::{Subscription, stream};
+use cosmic_bg_config::SamplingMethod;
+use cosmic_greeter_daemon::{BgEntry, BgSource};
+use std::any::TypeId;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+
+/// Time it takes to fade from one image to the next
+pub const FADE: Duration = Duration::from_millis(1000);
+
+/// Images of a cosmic-bg directory source, shown one after another
+#[derive(Clone, Debug)]
+pub struct Slideshow {
+    pub dir: PathBuf,
+    pub rotation: Duration,
+    images: Vec<PathBuf>,
+    index: usize,
+}
+
+impl Slideshow {
+    /// Returns `None` if the entry is not a directory source that rotates
+    pub fn new(entry: &BgEntry, current_opt: Option<&BgSource>) -> Option<Self> {
+        let BgSource::Path(dir) = &entry.source else {
+            return None;
+        };
+        if entry.rotation_frequency == 0 || !dir.is_dir() {
+            return None;
+        }
+
+        let mut images = Vec::new();
+        if let Err(err) = find_images(dir, &mut images) {
+            tracing::warn!("failed to read wallpaper directory {:?}: {}", dir, err);
+        }
+        Self::from_images(
+            dir.clone(),
+            Duration::from_secs(entry.rotation_frequency),
+            images,
+            &entry.sampling_method,
+            current_opt,
+        )
+    }
+
+    /// Returns `None` if there are less than two images to rotate between
+    fn from_images(
+        dir: PathBuf,
+        rotation: Duration,
+        mut images: Vec<PathBuf>,
+        sampling_method: &SamplingMethod,
+        current_opt: Option<&BgSource>,
+    ) -> Option<Self> {
+        // Use the same ordering as cosmic-bg
+        match sampling_method {
+            SamplingMethod::Alphanumeric => images.sort(),
+            SamplingMethod::Random => shuffle(&mut images),
+        }
+        if images.len() < 2 {
+            return None;
+        }
+
+        // Continue from the image cosmic-bg showed last
+        let index = match current_opt {
+            Some(BgSource::Path(current)) => images
+                .iter()
+                .position(|image| image == current)
+                .unwrap_or_default(),
+            _ => 0,
+        };
+
+        Some(Self {
+            dir,
+            rotation,
+            images,
+            index,
+        })
+    }
+
+    /// Moves to the next image and returns its path
+    pub fn next(&mut self) -> &Path {
+        self.index = (self.index + 1) % self.images.len();
+        &self.images[self.index]
+    }
+
+    /// Emits the output name whenever the slideshow should move to the next image
+    pub fn subscription(&self, output_name: &str) -> Subscription<String> {
+        struct SlideshowSubscription;
+        Subscription::run_with(
+            (
+                TypeId::of::<SlideshowSubscription>(),
+                output_name.to_string(),
+                self.rotation,
+            ),
+            |(_, output_name, rotation)| {
+                let output_name = output_name.clone();
+                let rotation = *rotation;
+                stream::channel(1, move |mut msg_tx| async move {
+                    let start = tokio::time::Instant::now() + rotation;
+                    let mut interval = tokio::time::interval_at(start, rotation);
+                    loop {
+                        interval.tick().await;
+                        if msg_tx.send(output_name.clone()).await.is_err() {
+                            break;
+                        }
+                    }
+                })
+            },
+        )
+    }
+}
+
+fn is_image(path: &Path) -> bool {
+    image::ImageFormat::from_path(path).is_ok() || path.extension().is_some_and(|ext| ext == "jxl")
+}
+
+fn find_images(dir: &Path, images: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry_res in fs::read_dir(dir)? {
+        let entry = entry_res?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            find_images(&path, images)?;
+        } else if is_image(&path)
+            // Symlinked images are followed, symlinked directories are not to avoid cycles
+            && (file_type.is_file()
+                || (file_type.is_symlink()
+                    && fs::metadata(&path).is_ok_and(|metadata| metadata.is_file())))
+        {
+            images.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Fisher-Yates shuffle using xorshift seeded from the time, which is enough to pick an order
+/// of wallpapers
+fn shuffle(images: &mut [PathBuf]) {
+    let mut state = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos() as u64)
+        | 1;
+    for i in (1..images.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let j = (state % (i as u64 + 1)) as usize;
+        images.swap(i, j);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an empty directory for a test, removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "cosmic-greeter-slideshow-{}-{name}",
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn images(dir: &Path) -> Vec<PathBuf> {
+        let mut images = Vec::new();
+        find_images(dir, &mut images).unwrap();
+        images.sort();
+        images
+    }
+
+    #[test]
+    fn find_images_filters_and_recurses() {
+        let dir = TempDir::new("filter");
+        let outside = TempDir::new("filter-outside");
+        fs::create_dir(dir.0.join("nested")).unwrap();
+        for name in [
+            "b.png",
+            "a.jpg",
+            "nested/c.webp",
+            "d.jxl",
+            "notes.txt",
+            "no-ext",
+        ] {
+            fs::write(dir.0.join(name), b"").unwrap();
+        }
+        fs::write(outside.0.join("linked.png"), b"").unwrap();
+        std::os::unix::fs::symlink(outside.0.join("linked.png"), dir.0.join("e.png")).unwrap();
+        std::os::unix::fs::symlink(dir.0.join("missing.png"), dir.0.join("broken.png")).unwrap();
+        // Directory symlinks are not followed, this one would recurse forever
+        std::os::unix::fs::symlink(&dir.0, dir.0.join("loop")).unwrap();
+
+        assert_eq!(
+            images(&dir.0),
+            ["a.jpg", "b.png", "d.jxl", "e.png", "nested/c.webp"]
+                .into_iter()
+                .map(|name| dir.0.join(name))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn alphanumeric_order_and_advance() {
+        let dir = TempDir::new("advance");
+        for name in ["c.png", "a.png", "b.png"] {
+            fs::write(dir.0.join(name), b"").unwrap();
+        }
+        let mut found = Vec::new();
+        find_images(&dir.0, &mut found).unwrap();
+
+        let current = BgSource::Path(dir.0.join("b.png"));
+        let mut slideshow = Slideshow::from_images(
+            dir.0.clone(),
+            Duration::from_secs(60),
+            found,
+            &SamplingMethod::Alphanumeric,
+            Some(&current),
+        )
+        .unwrap();
+        // Continues after the current image and wraps around
+        assert_eq!(slideshow.next(), dir.0.join("c.png"));
+        assert_eq!(slideshow.next(), dir.0.join("a.png"));
+        assert_eq!(slideshow.next(), dir.0.join("b.png"));
+    }
+
+    #[test]
+    fn unknown_current_starts_at_first() {
+        let images = vec![PathBuf::from("/b.png"), PathBuf::from("/a.png")];
+        let current = BgSource::Path(PathBuf::from("/other.png"));
+        let mut slideshow = Slideshow::from_images(
+            PathBuf::from("/"),
+            Duration::from_secs(60),
+            images,
+            &SamplingMethod::Alphanumeric,
+            Some(&current),
+        )
+        .unwrap();
+        assert_eq!(slideshow.next(), Path::new("/b.png"));
+    }
+
+    #[test]
+    fn single_image_is_not_a_slideshow() {
+        assert!(
+            Slideshow::from_images(
+                PathBuf::from("/"),
+                Duration::from_secs(60),
+                vec![PathBuf::from("/a.png")],
+                &SamplingMethod::Alphanumeric,
+                None,
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn shuffle_keeps_images() {
+        let mut images = (0..32)
+            .map(|i| PathBuf::from(format!("/{i}.png")))
+            .collect::<Vec<_>>();
+        let mut shuffled = images.clone();
+        shuffle(&mut shuffled);
+        shuffled.sort();
+        images.sort();
+        assert_eq!(shuffled, images);
+    }
+}
@@ -2,12 +2,24 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 use clap_lex::RawArgs;
-use cosmic_greeter::{greeter, locker};
+use cosmic_greeter::{greeter, locker, x11};
 use std::error::Error;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let raw_args = RawArgs::from_args();
     let mut cursor = raw_args.cursor();
+    // Skip the binary name
+    raw_args.next_os(&mut cursor);
+
+    // The X11 launcher passes all remaining arguments to the session
+    if raw_args.peek_os(&cursor).and_then(|arg| arg.to_str()) == Some(x11::ARG) {
+        raw_args.next_os(&mut cursor);
+        let session_args = raw_args
+            .remaining(&mut cursor)
+            .map(|arg| arg.to_os_string())
+            .collect();
+        return x11::main(session_args);
+    }
 
     // Parse the arguments
     while let Some(arg) = raw_args.next_os(&mut cursor) {
@@ -0,0 +1,107 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Argument of cosmic-greeter that runs the launcher, followed by the session command
+pub const ARG: &str = "x11-session";
+
+/// Family of Xauthority entries that match any address
+const FAMILY_WILD: u16 = 0xFFFF;
+const COOKIE_NAME: &str = "MIT-MAGIC-COOKIE-1";
+
+/// Finds the first display without a lock file or socket
+fn free_display() -> Option<u32> {
+    (0..64).find(|display| {
+        !Path::new(&format!("/tmp/.X{display}-lock")).exists()
+            && !Path::new(&format!("/tmp/.X11-unix/X{display}")).exists()
+    })
+}
+
+/// The file is removed when the session ends, so it must not be the user's `~/.Xauthority`
+fn xauthority_path(display: u32) -> Option<PathBuf> {
+    dirs::runtime_dir()
+        .or_else(dirs::home_dir)
+        .map(|dir| dir.join(format!(".cosmic-greeter-xauth-{display}")))
+}
+
+/// Writes an Xauthority file with a new cookie for the display, readable only by the user
+fn write_xauthority(path: &Path, display: u32) -> Result<(), Box<dyn Error>> {
+    let mut cookie = [0; 16];
+    fs::File::open("/dev/urandom")?.read_exact(&mut cookie)?;
+
+    let number = display.to_string();
+    let mut entry = Vec::new();
+    entry.extend_from_slice(&FAMILY_WILD.to_be_bytes());
+    // Address, display number, authorization name and data, each prefixed by their length
+    for field in [
+        &[][..],
+        number.as_bytes(),
+        COOKIE_NAME.as_bytes(),
+        &cookie[..],
+    ] {
+        entry.extend_from_slice(&u16::try_from(field.len())?.to_be_bytes());
+        entry.extend_from_slice(field);
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(&entry)?;
+    Ok(())
+}
+
+/// Launcher for X11 sessions. greetd runs it as the session command of the user, on the VT of
+/// the greeter. It starts an X server on that VT with xinit, which runs the session command.
+pub fn main(session_args: Vec<OsString>) -> Result<(), Box<dyn Error>> {
+    // xinit treats a first argument that is not a path as arguments for xterm
+    if session_args
+        .first()
+        .is_none_or(|arg| !Path::new(arg).is_absolute())
+    {
+        return Err(format!("{ARG} requires an absolute session command").into());
+    }
+
+    let display = free_display().ok_or("no free X display")?;
+    let auth_path = xauthority_path(display).ok_or("no runtime or home directory")?;
+    write_xauthority(&auth_path, display)?;
+
+    let mut command = Command::new("xinit");
+    command
+        .args(&session_args)
+        .arg("--")
+        .arg(format!(":{display}"));
+    // greetd sets the VT of the session, the server has to use it to get access to input
+    // devices through logind
+    match std::env::var("XDG_VTNR") {
+        Ok(vt) => {
+            command.arg(format!("vt{vt}"));
+        }
+        Err(err) => {
+            eprintln!("XDG_VTNR not set, X server picks a VT: {err}");
+        }
+    }
+    command
+        .args(["-keeptty", "-nolisten", "tcp", "-auth"])
+        .arg(&auth_path)
+        .env("XAUTHORITY", &auth_path);
+
+    let status_res = command.status();
+    if let Err(err) = fs::remove_file(&auth_path) {
+        eprintln!("failed to remove {}: {err}", auth_path.display());
+    }
+    let status = status_res?;
+    if !status.success() {
+        return Err(format!("xinit exited with {status}").into());
+    }
+    Ok(())
+}